@@ -0,0 +1,312 @@
+use crate::cache::{DigiSound, DIGI_SAMPLE_RATE};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const OUTPUT_RATE: u32 = 44100;
+
+/// Digitized sound numbers of the game sounds that have one, as in the original's digi map.
+pub const CLOSE_DOOR_DIGI: usize = 2;
+pub const OPEN_DOOR_DIGI: usize = 3;
+pub const PUSH_WALL_DIGI: usize = 15;
+
+/// The mixer plays one sound at a time on each of these, like the original sound manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Digitized,
+    Effects,
+    Music,
+}
+
+const CHANNELS: [Channel; 3] = [Channel::Digitized, Channel::Effects, Channel::Music];
+
+/// Signed 16 bit mono samples at a given rate, ready to be mixed.
+#[derive(Debug, Clone)]
+pub struct Sound {
+    pub rate: u32,
+    pub samples: Vec<i16>,
+}
+
+impl Sound {
+    pub fn from_digi(sound: &DigiSound) -> Self {
+        Sound {
+            rate: DIGI_SAMPLE_RATE,
+            samples: sound
+                .samples
+                .iter()
+                .map(|&s| (s as i16 - 128) << 8)
+                .collect(),
+        }
+    }
+}
+
+/// Somewhere to send the mixed output to, as signed 16 bit mono samples.
+pub trait AudioSink {
+    fn write(&mut self, samples: &[i16]) -> io::Result<()>;
+
+    /// Called once no more samples will be written.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Voice {
+    sound: Sound,
+    /// Position within the sound, in samples of the sound's own rate.
+    position: f64,
+    looping: bool,
+}
+
+struct ChannelState {
+    volume: f32,
+    voice: Option<Voice>,
+}
+
+/// Mixes the sounds playing on each channel into a single stream at the output rate.
+pub struct Mixer {
+    rate: u32,
+    channels: [ChannelState; 3],
+}
+
+impl Mixer {
+    pub fn new(rate: u32) -> Self {
+        Mixer {
+            rate,
+            channels: CHANNELS.map(|_| ChannelState {
+                volume: 1.0,
+                voice: None,
+            }),
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Sets the volume of a channel, from 0.0 (muted) to 1.0.
+    pub fn set_volume(&mut self, channel: Channel, volume: f32) {
+        self.channel(channel).volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Starts playing a sound on a channel, replacing whatever was playing there.
+    pub fn play(&mut self, channel: Channel, sound: Sound, looping: bool) {
+        self.channel(channel).voice = Some(Voice {
+            sound,
+            position: 0.0,
+            looping,
+        });
+    }
+
+    pub fn stop(&mut self, channel: Channel) {
+        self.channel(channel).voice = None;
+    }
+
+    pub fn is_playing(&self, channel: Channel) -> bool {
+        self.channels[channel as usize].voice.is_some()
+    }
+
+    /// Mixes the next `frames` samples of all the channels.
+    pub fn mix(&mut self, frames: usize) -> Vec<i16> {
+        let mut output = vec![0i32; frames];
+        for state in self.channels.iter_mut() {
+            let volume = state.volume;
+            if let Some(voice) = &mut state.voice {
+                let finished = voice.render(self.rate, volume, &mut output);
+                if finished {
+                    state.voice = None;
+                }
+            }
+        }
+        output
+            .into_iter()
+            .map(|s| s.clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+            .collect()
+    }
+
+    /// Mixes the next `frames` samples and writes them to `sink`.
+    pub fn render(&mut self, sink: &mut dyn AudioSink, frames: usize) -> io::Result<()> {
+        let samples = self.mix(frames);
+        sink.write(&samples)
+    }
+
+    fn channel(&mut self, channel: Channel) -> &mut ChannelState {
+        &mut self.channels[channel as usize]
+    }
+}
+
+impl Voice {
+    /// Adds this voice to `output`, resampled to `rate`. Returns true once the sound is over.
+    fn render(&mut self, rate: u32, volume: f32, output: &mut [i32]) -> bool {
+        let samples = &self.sound.samples;
+        if samples.is_empty() {
+            return true;
+        }
+        let step = self.sound.rate as f64 / rate as f64;
+
+        for out in output.iter_mut() {
+            if self.position >= samples.len() as f64 {
+                if !self.looping {
+                    return true;
+                }
+                // a short sound played at a high rate can go past its end more than once
+                self.position %= samples.len() as f64;
+            }
+
+            // linear interpolation between the two closest samples
+            let index = self.position as usize;
+            let fract = self.position - index as f64;
+            let current = samples[index] as f64;
+            let next = match samples.get(index + 1) {
+                Some(&s) => s as f64,
+                None if self.looping => samples[0] as f64,
+                None => current,
+            };
+            let sample = current + (next - current) * fract;
+
+            *out += (sample * volume as f64) as i32;
+            self.position += step;
+        }
+        !self.looping && self.position >= samples.len() as f64
+    }
+}
+
+/// Writes the mixed audio to a 16 bit mono WAV file, for headless runs.
+pub struct WavSink<W: Write + Seek> {
+    writer: W,
+    data_len: u32,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, rate: u32) -> io::Result<Self> {
+        WavSink::new(BufWriter::new(File::create(path)?), rate)
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W, rate: u32) -> io::Result<Self> {
+        // the RIFF and data chunk sizes are filled in by finish
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&1u16.to_le_bytes())?; // mono
+        writer.write_all(&rate.to_le_bytes())?;
+        writer.write_all(&(rate * 2).to_le_bytes())?; // bytes per second
+        writer.write_all(&2u16.to_le_bytes())?; // bytes per frame
+        writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(WavSink {
+            writer,
+            data_len: 0,
+        })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sound(rate: u32, samples: &[i16]) -> Sound {
+        Sound {
+            rate,
+            samples: samples.to_vec(),
+        }
+    }
+
+    #[test]
+    fn digitized_sounds_are_centered() {
+        let digi = DigiSound {
+            samples: vec![0, 128, 255],
+        };
+        assert_eq!(vec![-32768, 0, 32512], Sound::from_digi(&digi).samples);
+    }
+
+    #[test]
+    fn mix_resamples_to_output_rate() {
+        let mut mixer = Mixer::new(4);
+        mixer.play(Channel::Digitized, sound(2, &[0, 100, 200]), false);
+        assert_eq!(vec![0, 50, 100, 150, 200, 200, 0, 0], mixer.mix(8));
+        assert!(!mixer.is_playing(Channel::Digitized));
+    }
+
+    #[test]
+    fn mix_adds_channels_with_their_volume() {
+        let mut mixer = Mixer::new(10);
+        mixer.set_volume(Channel::Music, 0.5);
+        mixer.play(Channel::Effects, sound(10, &[1000, 1000]), false);
+        mixer.play(Channel::Music, sound(10, &[400]), true);
+        assert_eq!(vec![1200, 1200, 200], mixer.mix(3));
+        assert!(mixer.is_playing(Channel::Music));
+        assert!(!mixer.is_playing(Channel::Effects));
+    }
+
+    #[test]
+    fn short_loops_wrap_around_at_high_rates() {
+        let mut mixer = Mixer::new(10);
+        mixer.play(Channel::Digitized, sound(35, &[500]), true);
+        assert_eq!(vec![500; 4], mixer.mix(4));
+        assert!(mixer.is_playing(Channel::Digitized));
+    }
+
+    #[test]
+    fn mix_clamps_the_output() {
+        let mut mixer = Mixer::new(10);
+        mixer.play(Channel::Effects, sound(10, &[30000]), false);
+        mixer.play(Channel::Digitized, sound(10, &[30000]), false);
+        assert_eq!(vec![i16::MAX], mixer.mix(1));
+    }
+
+    #[test]
+    fn play_replaces_the_channel_sound() {
+        let mut mixer = Mixer::new(10);
+        mixer.play(Channel::Effects, sound(10, &[5, 5, 5]), false);
+        mixer.mix(1);
+        mixer.play(Channel::Effects, sound(10, &[7]), false);
+        assert_eq!(vec![7, 0], mixer.mix(2));
+    }
+
+    #[test]
+    fn wav_sink_writes_header_and_samples() {
+        let mut mixer = Mixer::new(8000);
+        mixer.play(Channel::Effects, sound(8000, &[1, -2]), false);
+        let mut sink = WavSink::new(Cursor::new(Vec::new()), 8000).unwrap();
+        mixer.render(&mut sink, 3).unwrap();
+        sink.finish().unwrap();
+
+        let wav = sink.into_inner().into_inner();
+        assert_eq!(44 + 6, wav.len());
+        assert_eq!(b"RIFF", &wav[0..4]);
+        assert_eq!(42, u32::from_le_bytes(wav[4..8].try_into().unwrap()));
+        assert_eq!(b"WAVEfmt ", &wav[8..16]);
+        assert_eq!(8000, u32::from_le_bytes(wav[24..28].try_into().unwrap()));
+        assert_eq!(b"data", &wav[36..40]);
+        assert_eq!(6, u32::from_le_bytes(wav[40..44].try_into().unwrap()));
+        assert_eq!(&[1, 0, 0xFE, 0xFF, 0, 0], &wav[44..]);
+    }
+}
@@ -1,7 +1,14 @@
-use crate::constants::{MAP_HEIGHT, MAP_WIDTH};
+use crate::constants::{WALLPIC_HEIGHT, WALLPIC_WIDTH};
 use crate::map::Map;
-use std::fs;
-use std::path::Path;
+use crate::mod_dir::ModDir;
+use std::cell::OnceCell;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 
 pub const H_BJPIC: usize = 3;
 pub const H_CASTLEPIC: usize = 4;
@@ -148,186 +155,1639 @@ pub const MUTANTBJPIC: usize = 144;
 pub const PAUSEDPIC: usize = 145;
 pub const GETPSYCHEDPIC: usize = 146;
 
+pub const STARTTILE8: usize = 147;
+pub const NUMTILE8: usize = 72;
+pub const STARTEXTERNS: usize = 148;
+pub const ORDERSCREEN: usize = 148;
+pub const ERRORSCREEN: usize = 149;
+pub const T_HELPART: usize = 150;
+pub const T_DEMO0: usize = 151;
+pub const T_DEMO1: usize = 152;
+pub const T_DEMO2: usize = 153;
+pub const T_DEMO3: usize = 154;
+pub const T_ENDART1: usize = 155;
+pub const T_ENDART2: usize = 156;
+pub const T_ENDART3: usize = 157;
+pub const T_ENDART4: usize = 158;
+pub const T_ENDART5: usize = 159;
+pub const T_ENDART6: usize = 160;
+
+// sprite numbers as in the original sprite enum, see `Cache::get_sprite`
+pub const SPR_STAT_0: usize = 2;
+pub const SPR_KNIFEREADY: usize = 416;
+pub const SPR_PISTOLREADY: usize = 421;
+pub const SPR_MACHINEGUNREADY: usize = 426;
+pub const SPR_CHAINREADY: usize = 431;
+
 pub const NUMCHUNKS: u32 = 161;
+pub const STARTFONT: usize = 1;
+pub const NUMFONT: usize = 2;
 pub const NUMPICS: usize = 144;
 pub const STARTPICS: usize = 3;
 
+/// Names of the pictures, as in the constants above, starting at `STARTPICS`.
+const PIC_NAMES: [&str; NUMPICS] = [
+    "H_BJPIC",
+    "H_CASTLEPIC",
+    "H_KEYBOARDPIC",
+    "H_JOYPIC",
+    "H_HEALPIC",
+    "H_TREASUREPIC",
+    "H_GUNPIC",
+    "H_KEYPIC",
+    "H_BLAZEPIC",
+    "H_WEAPON1234PIC",
+    "H_WOLFLOGOPIC",
+    "H_VISAPIC",
+    "H_MCPIC",
+    "H_IDLOGOPIC",
+    "H_TOPWINDOWPIC",
+    "H_LEFTWINDOWPIC",
+    "H_RIGHTWINDOWPIC",
+    "H_BOTTOMINFOPIC",
+    "H_SPEARADPIC",
+    "C_OPTIONSPIC",
+    "C_CURSOR1PIC",
+    "C_CURSOR2PIC",
+    "C_NOTSELECTEDPIC",
+    "C_SELECTEDPIC",
+    "C_FXTITLEPIC",
+    "C_DIGITITLEPIC",
+    "C_MUSICTITLEPIC",
+    "C_MOUSELBACKPIC",
+    "C_BABYMODEPIC",
+    "C_EASYPIC",
+    "C_NORMALPIC",
+    "C_HARDPIC",
+    "C_LOADSAVEDISKPIC",
+    "C_DISKLOADING1PIC",
+    "C_DISKLOADING2PIC",
+    "C_CONTROLPIC",
+    "C_CUSTOMIZEPIC",
+    "C_LOADGAMEPIC",
+    "C_SAVEGAMEPIC",
+    "C_EPISODE1PIC",
+    "C_EPISODE2PIC",
+    "C_EPISODE3PIC",
+    "C_EPISODE4PIC",
+    "C_EPISODE5PIC",
+    "C_EPISODE6PIC",
+    "C_CODEPIC",
+    "C_TIMECODEPIC",
+    "C_LEVELPIC",
+    "C_NAMEPIC",
+    "C_SCOREPIC",
+    "C_JOY1PIC",
+    "C_JOY2PIC",
+    "L_GUYPIC",
+    "L_COLONPIC",
+    "L_NUM0PIC",
+    "L_NUM1PIC",
+    "L_NUM2PIC",
+    "L_NUM3PIC",
+    "L_NUM4PIC",
+    "L_NUM5PIC",
+    "L_NUM6PIC",
+    "L_NUM7PIC",
+    "L_NUM8PIC",
+    "L_NUM9PIC",
+    "L_PERCENTPIC",
+    "L_APIC",
+    "L_BPIC",
+    "L_CPIC",
+    "L_DPIC",
+    "L_EPIC",
+    "L_FPIC",
+    "L_GPIC",
+    "L_HPIC",
+    "L_IPIC",
+    "L_JPIC",
+    "L_KPIC",
+    "L_LPIC",
+    "L_MPIC",
+    "L_NPIC",
+    "L_OPIC",
+    "L_PPIC",
+    "L_QPIC",
+    "L_RPIC",
+    "L_SPIC",
+    "L_TPIC",
+    "L_UPIC",
+    "L_VPIC",
+    "L_WPIC",
+    "L_XPIC",
+    "L_YPIC",
+    "L_ZPIC",
+    "L_EXPOINTPIC",
+    "L_APOSTROPHEPIC",
+    "L_GUY2PIC",
+    "L_BJWINSPIC",
+    "STATUSBARPIC",
+    "TITLEPIC",
+    "PG13PIC",
+    "CREDITSPIC",
+    "HIGHSCORESPIC",
+    "KNIFEPIC",
+    "GUNPIC",
+    "MACHINEGUNPIC",
+    "GATLINGGUNPIC",
+    "NOKEYPIC",
+    "GOLDKEYPIC",
+    "SILVERKEYPIC",
+    "N_BLANKPIC",
+    "N_0PIC",
+    "N_1PIC",
+    "N_2PIC",
+    "N_3PIC",
+    "N_4PIC",
+    "N_5PIC",
+    "N_6PIC",
+    "N_7PIC",
+    "N_8PIC",
+    "N_9PIC",
+    "FACE1APIC",
+    "FACE1BPIC",
+    "FACE1CPIC",
+    "FACE2APIC",
+    "FACE2BPIC",
+    "FACE2CPIC",
+    "FACE3APIC",
+    "FACE3BPIC",
+    "FACE3CPIC",
+    "FACE4APIC",
+    "FACE4BPIC",
+    "FACE4CPIC",
+    "FACE5APIC",
+    "FACE5BPIC",
+    "FACE5CPIC",
+    "FACE6APIC",
+    "FACE6BPIC",
+    "FACE6CPIC",
+    "FACE7APIC",
+    "FACE7BPIC",
+    "FACE7CPIC",
+    "FACE8APIC",
+    "GOTGATLINGPIC",
+    "MUTANTBJPIC",
+    "PAUSEDPIC",
+    "GETPSYCHEDPIC",
+];
+
 const NUM_MAPS: usize = 60;
+const TILE8_SIZE: usize = 8;
+const TEXT_COLUMNS: usize = 80;
+/// Text screens are saved with BSAVE, which puts a 7 byte header in front.
+const BSAVE_HEADER_SIZE: usize = 7;
+const PALETTE_SIZE: usize = 256 * 3;
+const HUFF_NODES: usize = 255;
+const SPRITE_WIDTH: usize = 64;
+/// VGAHEAD holds a 3 byte offset per chunk, plus one where the last chunk ends.
+pub const GRAPHICS_OFFSET_SIZE: usize = 3;
+
+/// Base names of the data files that make up a complete game data set.
+pub const DATA_FILES: [&str; 8] = [
+    "VSWAP", "GAMEMAPS", "MAPHEAD", "VGAGRAPH", "VGAHEAD", "VGADICT", "AUDIOHED", "AUDIOT",
+];
+
+/// The releases of the game whose data files we know about, told apart by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameVariant {
+    Shareware,
+    Registered,
+    SpearOfDestiny,
+}
+
+impl GameVariant {
+    // when more than one data set is found in the same directory, the first one here wins
+    const ALL: [GameVariant; 3] = [
+        GameVariant::Shareware,
+        GameVariant::Registered,
+        GameVariant::SpearOfDestiny,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GameVariant::Shareware => "WL1",
+            GameVariant::Registered => "WL6",
+            GameVariant::SpearOfDestiny => "SOD",
+        }
+    }
+
+    /// Number of sound effects in each of the sound banks of AUDIOT.
+    fn num_sounds(&self) -> usize {
+        match self {
+            GameVariant::Shareware | GameVariant::Registered => 87,
+            GameVariant::SpearOfDestiny => 81,
+        }
+    }
+}
+
+/// Returns the name of a picture chunk, e.g. "TITLEPIC" for `TITLEPIC`.
+pub fn pic_name(chunk: usize) -> Option<&'static str> {
+    chunk
+        .checked_sub(STARTPICS)
+        .and_then(|i| PIC_NAMES.get(i))
+        .copied()
+}
+
+/// What a graphics chunk after the 8x8 tiles holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternKind {
+    TextScreen,
+    Article,
+    Demo,
+    Palette,
+}
+
+/// The chunks after the 8x8 tiles of the Wolfenstein 3D releases, `ORDERSCREEN` to
+/// `T_ENDART6`.
+const WOLF_EXTERNS: [ExternKind; 13] = [
+    ExternKind::TextScreen,
+    ExternKind::TextScreen,
+    ExternKind::Article,
+    ExternKind::Demo,
+    ExternKind::Demo,
+    ExternKind::Demo,
+    ExternKind::Demo,
+    ExternKind::Article,
+    ExternKind::Article,
+    ExternKind::Article,
+    ExternKind::Article,
+    ExternKind::Article,
+    ExternKind::Article,
+];
+
+/// The chunks after the 8x8 tiles of Spear of Destiny: the error screen, the palettes of
+/// the title, the 9 end screens and the id guys picture, then the demos and the end text.
+const SPEAR_EXTERNS: [ExternKind; 17] = [
+    ExternKind::TextScreen,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Palette,
+    ExternKind::Demo,
+    ExternKind::Demo,
+    ExternKind::Demo,
+    ExternKind::Demo,
+    ExternKind::Article,
+];
+
+/// Where each kind of graphics chunk starts. Chunk 0 is the picture table and the fonts
+/// come next, then the pictures, the 8x8 tiles and the rest. Releases have a different
+/// number of pictures, which moves everything after them, and different chunks after
+/// the tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLayout {
+    pub num_pics: usize,
+    /// The kind of each chunk after the 8x8 tiles, empty if they're not known.
+    pub externs: &'static [ExternKind],
+}
 
-const DATADIR: &str = "data";
+impl ChunkLayout {
+    /// The layout the chunk constants in this file are for.
+    pub const SHAREWARE: ChunkLayout = ChunkLayout::with_externs(NUMPICS, &WOLF_EXTERNS);
+    /// The layout of the registered v1.4 releases.
+    pub const REGISTERED: ChunkLayout = ChunkLayout::with_externs(132, &WOLF_EXTERNS);
+    /// The layout of Spear of Destiny v1.0.
+    pub const SPEAR_OF_DESTINY: ChunkLayout = ChunkLayout::with_externs(147, &SPEAR_EXTERNS);
+
+    /// A layout of which only the number of pictures is known.
+    pub const fn new(num_pics: usize) -> Self {
+        ChunkLayout::with_externs(num_pics, &[])
+    }
+
+    pub const fn with_externs(num_pics: usize, externs: &'static [ExternKind]) -> Self {
+        ChunkLayout { num_pics, externs }
+    }
+
+    pub fn start_tile8(&self) -> usize {
+        STARTPICS + self.num_pics
+    }
+
+    pub fn start_externs(&self) -> usize {
+        self.start_tile8() + 1
+    }
+
+    /// The kind of an extern chunk and its index among the chunks of that kind.
+    pub fn extern_index(&self, chunk: usize) -> Option<(ExternKind, usize)> {
+        let i = chunk.checked_sub(self.start_externs())?;
+        let kind = *self.externs.get(i)?;
+        let index = self.externs[..i].iter().filter(|&&k| k == kind).count();
+        Some((kind, index))
+    }
+}
+
+/// Probes `data_dir` for the data files of each known variant and returns the first
+/// one for which all of them are present.
+pub fn detect_variant(data_dir: &Path) -> Option<GameVariant> {
+    GameVariant::ALL.into_iter().find(|&variant| {
+        DATA_FILES
+            .iter()
+            .all(|name| data_file(data_dir, name, variant).is_file())
+    })
+}
+
+/// Returns the path of the given data file for a variant, e.g. `data/VSWAP.WL1`.
+/// Lowercase file names are accepted as well, since that's how some distributions ship them.
+pub fn data_file(data_dir: &Path, name: &str, variant: GameVariant) -> PathBuf {
+    let file_name = format!("{}.{}", name, variant.extension());
+    let path = data_dir.join(&file_name);
+    if path.is_file() {
+        return path;
+    }
+    let lowercase = data_dir.join(file_name.to_lowercase());
+    if lowercase.is_file() {
+        lowercase
+    } else {
+        path
+    }
+}
 
+/// Gives access to the pictures, textures, sprites, sounds and maps of a data set.
+/// Only headers and chunk offsets are read by `init`, each chunk is decoded the first
+/// time it's asked for and kept around from then on.
 pub struct Cache {
-    pics: Vec<Picture>,
-    textures: Vec<Vec<u8>>,
-    sprites: Vec<(CompShape, Vec<u8>)>,
-    sounds: Vec<Vec<u8>>,
-    maps: Vec<Map>,
+    graphics: GraphicsFile,
+    vswap: VswapFile,
+    game_maps: GameMapsFile,
+    audio: AudioFile,
+    /// Loose files that replace textures, sprites, pictures and maps, checked first.
+    mod_dir: Option<ModDir>,
+    fonts: Vec<OnceCell<Font>>,
+    pics: Vec<OnceCell<Picture>>,
+    tile8: OnceCell<Vec<Picture>>,
+    // the chunks after the 8x8 tiles, each kind memoised on its own
+    text_screens: Vec<OnceCell<TextScreen>>,
+    articles: Vec<OnceCell<Article>>,
+    demos: Vec<OnceCell<Demo>>,
+    palettes: Vec<OnceCell<Palette>>,
+    textures: Vec<OnceCell<Vec<u8>>>,
+    sprites: Vec<OnceCell<Sprite>>,
+    sounds: Vec<OnceCell<DigiSound>>,
+    maps: Vec<OnceCell<Map>>,
+    pc_sounds: Vec<OnceCell<PcSound>>,
+    adlib_sounds: Vec<OnceCell<AdlibSound>>,
+    music: Vec<OnceCell<ImfSong>>,
 }
 
 impl Cache {
-    pub fn new(
-        pics: Vec<Picture>,
-        textures: Vec<Vec<u8>>,
-        sprites: Vec<(CompShape, Vec<u8>)>,
-        sounds: Vec<Vec<u8>>,
-        maps: Vec<Map>,
-    ) -> Cache {
-        Cache {
-            pics,
-            textures,
-            sprites,
-            sounds,
-            maps,
+    pub fn with_mod_dir(mut self, mod_dir: ModDir) -> Self {
+        self.mod_dir = Some(mod_dir);
+        self
+    }
+
+    /// Number of pictures, the first one being chunk `STARTPICS`.
+    pub fn num_pics(&self) -> usize {
+        self.pics.len()
+    }
+
+    pub fn layout(&self) -> ChunkLayout {
+        self.graphics.layout
+    }
+
+    /// The name of a picture chunk, only known for the layout of the chunk constants.
+    pub fn pic_name(&self, chunk: usize) -> Option<&'static str> {
+        if self.graphics.layout == ChunkLayout::SHAREWARE {
+            pic_name(chunk)
+        } else {
+            None
         }
     }
 
-    pub fn get_pic(&self, index: usize) -> &Picture {
-        &self.pics[index - 3]
+    pub fn num_textures(&self) -> usize {
+        self.textures.len()
     }
 
-    pub fn get_texture(&self, index: usize) -> &Vec<u8> {
-        &self.textures[index]
+    pub fn num_sprites(&self) -> usize {
+        self.sprites.len()
     }
 
-    pub fn get_sprite(&self, index: usize) -> &(CompShape, Vec<u8>) {
-        &self.sprites[index]
+    pub fn num_sounds(&self) -> usize {
+        self.sounds.len()
     }
 
-    pub fn get_sound(&self, index: usize) -> &Vec<u8> {
-        &self.sounds[index]
+    /// Returns font 0 (the small one) or 1 (the large one).
+    pub fn get_font(&self, index: usize) -> Result<&Font, CacheError> {
+        let chunk = STARTFONT + index;
+        let cell = self
+            .fonts
+            .get(index)
+            .ok_or_else(|| self.graphics.file.missing_chunk(chunk))?;
+        memoise(cell, || self.graphics.load_font(chunk))
     }
 
-    pub fn get_map(&self, episode: usize, level: usize) -> Map {
-        self.maps[level + 10 * episode].clone()
+    pub fn get_pic(&self, index: usize) -> Result<&Picture, CacheError> {
+        let cell = index
+            .checked_sub(STARTPICS)
+            .and_then(|i| self.pics.get(i))
+            .ok_or_else(|| self.graphics.file.missing_chunk(index))?;
+        memoise(cell, || {
+            // named like extract names them
+            let name = match self.pic_name(index) {
+                Some(name) => name.to_string(),
+                None => format!("{:03}", index),
+            };
+            if let Some(pic) = self.mod_override(|mod_dir| mod_dir.pic(&name))? {
+                return Ok(pic);
+            }
+            self.graphics.load_pic(index)
+        })
+    }
+
+    /// Returns one of the 8x8 tiles, which are stored planar like pictures.
+    pub fn get_tile8(&self, index: usize) -> Result<&Picture, CacheError> {
+        let tiles = memoise(&self.tile8, || self.graphics.load_tile8())?;
+        tiles.get(index).ok_or_else(|| {
+            self.graphics
+                .file
+                .missing_chunk(self.graphics.layout.start_tile8())
+        })
+    }
+
+    /// Returns a text mode screen, `ORDERSCREEN` or `ERRORSCREEN`.
+    pub fn get_text_screen(&self, chunk: usize) -> Result<&TextScreen, CacheError> {
+        self.get_extern(
+            &self.text_screens,
+            ExternKind::TextScreen,
+            chunk,
+            parse_text_screen,
+        )
+    }
+
+    /// Returns a text article, `T_HELPART` or one of the `T_ENDART*`.
+    pub fn get_article(&self, chunk: usize) -> Result<&Article, CacheError> {
+        self.get_extern(&self.articles, ExternKind::Article, chunk, |data| {
+            Some(parse_article(&data))
+        })
+    }
+
+    /// Returns a recorded demo, one of the `T_DEMO*`.
+    pub fn get_demo(&self, chunk: usize) -> Result<&Demo, CacheError> {
+        self.get_extern(&self.demos, ExternKind::Demo, chunk, parse_demo)
+    }
+
+    /// Returns a palette chunk. The shareware data set doesn't have any, the other
+    /// releases use them for their title and end screens.
+    pub fn get_palette(&self, chunk: usize) -> Result<&Palette, CacheError> {
+        self.get_extern(&self.palettes, ExternKind::Palette, chunk, parse_palette)
+    }
+
+    /// Loads an extern chunk of the given kind, any other chunk is missing as far as
+    /// `kind` is concerned.
+    fn get_extern<'a, T>(
+        &self,
+        cells: &'a [OnceCell<T>],
+        kind: ExternKind,
+        chunk: usize,
+        parse: impl FnOnce(Vec<u8>) -> Option<T>,
+    ) -> Result<&'a T, CacheError> {
+        let cell = match self.graphics.layout.extern_index(chunk) {
+            Some((chunk_kind, index)) if chunk_kind == kind => cells.get(index),
+            _ => None,
+        }
+        .ok_or_else(|| self.graphics.file.missing_chunk(chunk))?;
+        memoise(cell, || {
+            let data = self.graphics.load_chunk(chunk)?;
+            parse(data).ok_or_else(|| {
+                self.graphics
+                    .file
+                    .bad_header(self.graphics.headers[chunk] as usize, "bad chunk")
+            })
+        })
+    }
+
+    pub fn get_texture(&self, index: usize) -> Result<&Vec<u8>, CacheError> {
+        let cell = self
+            .textures
+            .get(index)
+            .ok_or_else(|| self.vswap.file.missing_chunk(index))?;
+        memoise(cell, || {
+            if let Some(texture) = self.mod_override(|mod_dir| mod_dir.texture(index))? {
+                return Ok(texture);
+            }
+            self.vswap.load_texture(index)
+        })
+    }
+
+    /// Returns the sprite with the given number, as listed in the original sprite enum,
+    /// e.g. `SPR_PISTOLREADY`.
+    pub fn get_sprite(&self, index: usize) -> Result<&Sprite, CacheError> {
+        let page = self.vswap.sprite_start + index;
+        let cell = self
+            .sprites
+            .get(index)
+            .ok_or_else(|| self.vswap.file.missing_chunk(page))?;
+        memoise(cell, || {
+            if let Some(sprite) = self.mod_override(|mod_dir| mod_dir.sprite(index))? {
+                return Ok(sprite);
+            }
+            self.vswap.load_sprite(page)
+        })
+    }
+
+    /// Returns the digitized sound with the given number.
+    pub fn get_sound(&self, index: usize) -> Result<&DigiSound, CacheError> {
+        let cell = self
+            .sounds
+            .get(index)
+            .ok_or_else(|| CacheError::MissingSound {
+                path: self.vswap.file.path.clone(),
+                index,
+                count: self.sounds.len(),
+            })?;
+        memoise(cell, || self.vswap.load_sound(index))
+    }
+
+    /// Number of map slots, 10 per episode. Not all of them hold a level.
+    pub fn num_maps(&self) -> usize {
+        self.maps.len()
+    }
+
+    pub fn get_map(&self, episode: usize, level: usize) -> Result<Map, CacheError> {
+        let index = level + 10 * episode;
+        let cell = self
+            .maps
+            .get(index)
+            .ok_or_else(|| self.game_maps.file.missing_chunk(index))?;
+        memoise(cell, || {
+            if let Some(map) = self.mod_override(|mod_dir| mod_dir.map(episode, level))? {
+                return Ok(map);
+            }
+            self.game_maps.load_map(index)
+        })
+        .cloned()
+    }
+
+    pub fn get_pc_sound(&self, index: usize) -> Result<&PcSound, CacheError> {
+        let cell = self
+            .pc_sounds
+            .get(index)
+            .ok_or_else(|| self.audio.file.missing_chunk(index))?;
+        memoise(cell, || self.audio.load_pc_sound(index))
+    }
+
+    pub fn get_adlib_sound(&self, index: usize) -> Result<&AdlibSound, CacheError> {
+        let cell = self
+            .adlib_sounds
+            .get(index)
+            .ok_or_else(|| self.audio.file.missing_chunk(index))?;
+        memoise(cell, || self.audio.load_adlib_sound(index))
+    }
+
+    pub fn get_music(&self, index: usize) -> Result<&ImfSong, CacheError> {
+        let cell = self
+            .music
+            .get(index)
+            .ok_or_else(|| self.audio.file.missing_chunk(index))?;
+        memoise(cell, || self.audio.load_music(index))
+    }
+
+    /// Looks for a replacement in the mod directory, if there's one.
+    fn mod_override<T>(
+        &self,
+        load: impl FnOnce(&ModDir) -> Result<Option<T>, CacheError>,
+    ) -> Result<Option<T>, CacheError> {
+        match &self.mod_dir {
+            Some(mod_dir) => load(mod_dir),
+            None => Ok(None),
+        }
     }
 }
 
+/// Returns the value in `cell`, decoding it first if it's the first time it's asked for.
+fn memoise<T>(
+    cell: &OnceCell<T>,
+    decode: impl FnOnce() -> Result<T, CacheError>,
+) -> Result<&T, CacheError> {
+    if let Some(value) = cell.get() {
+        return Ok(value);
+    }
+    let value = decode()?;
+    Ok(cell.get_or_init(|| value))
+}
+
+fn empty_cells<T>(count: usize) -> Vec<OnceCell<T>> {
+    (0..count).map(|_| OnceCell::new()).collect()
+}
+
 pub struct Picture {
     pub width: u32,
     pub height: u32,
     pub data: Vec<u8>,
 }
 
+/// A proportional font. `location` and `width` tell where each of the 256 glyphs
+/// starts in `data` and how wide it is. Glyphs are `height` rows of `width` bytes,
+/// non zero bytes being the pixels to draw.
 #[derive(Debug)]
-pub struct CompShape {
+pub struct Font {
+    pub height: u16,
+    pub location: Vec<u16>,
+    pub width: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+impl Font {
+    /// The glyph for a character, characters outside of the font showing as '?'.
+    pub fn glyph(&self, c: char) -> (u8, &[u8]) {
+        let index = if (c as u32) < 256 {
+            c as usize
+        } else {
+            '?' as usize
+        };
+        let width = self.width[index];
+        let start = self.location[index] as usize;
+        let end = start + width as usize * self.height as usize;
+        (width, self.data.get(start..end).unwrap_or_default())
+    }
+
+    /// Width of a string in pixels.
+    pub fn measure(&self, text: &str) -> u32 {
+        text.chars().map(|c| self.glyph(c).0 as u32).sum()
+    }
+}
+
+/// A screen of the 80 column text mode, such as the one shown when quitting the
+/// shareware game. Each cell is a character and its color attribute.
+#[derive(Debug)]
+pub struct TextScreen {
+    pub cells: Vec<(u8, u8)>,
+}
+
+impl TextScreen {
+    pub fn rows(&self) -> impl Iterator<Item = &[(u8, u8)]> {
+        self.cells.chunks(TEXT_COLUMNS)
+    }
+}
+
+/// A text article for the help and end screens, split in pages. The pages still hold
+/// the layout commands, which start with '^': `^C` to change color, `^G` to draw a
+/// picture, `^L` to move to a position and so on.
+#[derive(Debug)]
+pub struct Article {
+    pub pages: Vec<String>,
+}
+
+/// A demo recording: the map it plays in and the input for every tic.
+#[derive(Debug)]
+pub struct Demo {
+    pub map: u8,
+    pub commands: Vec<DemoCommand>,
+}
+
+/// The buttons held and the movement of the controls during one tic of a demo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DemoCommand {
+    pub buttons: u8,
+    pub dx: i8,
+    pub dy: i8,
+}
+
+/// 256 colors as 6 bit VGA DAC values.
+#[derive(Debug)]
+pub struct Palette {
+    pub colors: Vec<(u8, u8, u8)>,
+}
+
+/// A sprite, 64 pixels high, made of the columns from `left_pix` to `right_pix`.
+/// Each column is a list of spans of opaque pixels, everything else is transparent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sprite {
     pub left_pix: u16,
     pub right_pix: u16,
-    pub dataofs: Vec<u16>,
+    pub columns: Vec<Vec<Span>>,
+}
+
+/// Opaque pixels of a sprite column, starting at row `start`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub start: u16,
+    pub pixels: Vec<u8>,
 }
 
-pub fn init() -> Cache {
-    let huff_file = fs::read(DATADIR.to_owned() + "/VGADICT.WL1")
-        .expect("Something went wrong reading the file");
+impl Sprite {
+    /// The color at a position of the 64x64 sprite, `None` if it's transparent there.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<u8> {
+        let column = self.columns.get(x.checked_sub(self.left_pix as usize)?)?;
+        column.iter().find_map(|span| {
+            let row = y.checked_sub(span.start as usize)?;
+            span.pixels.get(row).copied()
+        })
+    }
+}
+
+/// The header of a sprite page: its first and last column, and the offset of
+/// the post commands of each column within the page.
+#[derive(Debug)]
+struct CompShape {
+    left_pix: u16,
+    right_pix: u16,
+    dataofs: Vec<u16>,
+}
+
+/// A digitized sound effect: unsigned 8 bit mono samples, played at `DIGI_SAMPLE_RATE`.
+#[derive(Debug, Clone)]
+pub struct DigiSound {
+    pub samples: Vec<u8>,
+}
 
-    let mut huff: Vec<(u16, u16)> = Vec::new();
+pub const DIGI_SAMPLE_RATE: u32 = 7000;
 
-    for i in huff_file.chunks_exact(4) {
-        let bit0 = u16::from_le_bytes([i[0], i[1]]);
-        let bit1 = u16::from_le_bytes([i[2], i[3]]);
-        huff.push((bit0, bit1));
+/// A PC speaker sound effect. Each byte of `data` is played for one 140Hz tick, and
+/// holds the timer divisor of the tone to play divided by 60, or 0 for silence.
+#[derive(Debug, Clone)]
+pub struct PcSound {
+    pub priority: u16,
+    pub data: Vec<u8>,
+}
+
+/// The OPL2 register values of an AdLib instrument: `m_*` go to the modulator
+/// operator and `c_*` to the carrier one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Instrument {
+    pub m_char: u8,
+    pub c_char: u8,
+    pub m_scale: u8,
+    pub c_scale: u8,
+    pub m_attack: u8,
+    pub c_attack: u8,
+    pub m_sus: u8,
+    pub c_sus: u8,
+    pub m_wave: u8,
+    pub c_wave: u8,
+    pub n_conn: u8,
+    pub voice: u8,
+    pub mode: u8,
+}
+
+/// An AdLib sound effect. Each byte of `data` is played for one 140Hz tick, and holds
+/// the low 8 bits of the frequency number to play in octave `block`, or 0 for silence.
+#[derive(Debug, Clone)]
+pub struct AdlibSound {
+    pub priority: u16,
+    pub instrument: Instrument,
+    pub block: u8,
+    pub data: Vec<u8>,
+}
+
+/// A write of `value` to OPL2 register `register`, followed by a wait of `delay`
+/// ticks of the 700Hz IMF clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImfCommand {
+    pub register: u8,
+    pub value: u8,
+    pub delay: u16,
+}
+
+/// A music track in IMF format.
+#[derive(Debug, Clone)]
+pub struct ImfSong {
+    pub commands: Vec<ImfCommand>,
+}
+
+/// Everything that can go wrong while reading the game data files.
+/// Offsets are byte positions within the file named by `path`.
+#[derive(Debug)]
+pub enum CacheError {
+    MissingFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    TruncatedChunk {
+        path: PathBuf,
+        offset: usize,
+        length: usize,
+    },
+    BadHeader {
+        path: PathBuf,
+        offset: usize,
+        reason: String,
+    },
+    DecompressionOverrun {
+        path: PathBuf,
+        offset: usize,
+    },
+    MissingChunk {
+        path: PathBuf,
+        index: usize,
+    },
+    /// A digitized sound number past the ones the sound info page of VSWAP lists.
+    MissingSound {
+        path: PathBuf,
+        index: usize,
+        count: usize,
+    },
+    /// A file of the mod directory that can't replace the asset it's named after.
+    BadModFile {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::MissingFile { path, source } => {
+                write!(f, "could not read {}: {}", path.display(), source)
+            }
+            CacheError::TruncatedChunk {
+                path,
+                offset,
+                length,
+            } => write!(
+                f,
+                "{}: chunk of {} bytes at offset {:#x} is truncated",
+                path.display(),
+                length,
+                offset
+            ),
+            CacheError::BadHeader {
+                path,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "{}: bad header at offset {:#x}: {}",
+                path.display(),
+                offset,
+                reason
+            ),
+            CacheError::DecompressionOverrun { path, offset } => write!(
+                f,
+                "{}: chunk at offset {:#x} decompresses past its bounds",
+                path.display(),
+                offset
+            ),
+            CacheError::MissingChunk { path, index } => {
+                write!(f, "{}: chunk {} is not present", path.display(), index)
+            }
+            CacheError::MissingSound { path, index, count } => write!(
+                f,
+                "{}: there is no digitized sound {}, the sound info page lists {}",
+                path.display(),
+                index,
+                count
+            ),
+            CacheError::BadModFile { path, reason } => write!(f, "{}: {}", path.display(), reason),
+        }
+    }
+}
+
+impl error::Error for CacheError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CacheError::MissingFile { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A data file on disk. Only its size is known up front, the bytes are read as needed.
+struct DataFile {
+    path: PathBuf,
+    len: usize,
+}
+
+impl DataFile {
+    fn open(path: PathBuf) -> Result<Self, CacheError> {
+        match fs::metadata(&path) {
+            Ok(metadata) => Ok(DataFile {
+                path,
+                len: metadata.len() as usize,
+            }),
+            Err(source) => Err(CacheError::MissingFile { path, source }),
+        }
+    }
+
+    /// Reads `length` bytes starting at `offset`, failing if the file is too short.
+    fn read(&self, offset: usize, length: usize) -> Result<Vec<u8>, CacheError> {
+        if !matches!(offset.checked_add(length), Some(end) if end <= self.len) {
+            return Err(self.truncated(offset, length));
+        }
+
+        let mut bytes = vec![0; length];
+        let read_bytes = |bytes: &mut [u8]| -> io::Result<()> {
+            let mut file = File::open(&self.path)?;
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.read_exact(bytes)
+        };
+        read_bytes(&mut bytes).map_err(|source| CacheError::MissingFile {
+            path: self.path.clone(),
+            source,
+        })?;
+        Ok(bytes)
+    }
+
+    fn read_all(&self) -> Result<Vec<u8>, CacheError> {
+        self.read(0, self.len)
+    }
+
+    fn read_u16(&self, offset: usize) -> Result<u16, CacheError> {
+        let bytes = self.read(offset, 2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32, CacheError> {
+        let bytes = self.read(offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn truncated(&self, offset: usize, length: usize) -> CacheError {
+        CacheError::TruncatedChunk {
+            path: self.path.clone(),
+            offset,
+            length,
+        }
+    }
+
+    fn bad_header(&self, offset: usize, reason: &str) -> CacheError {
+        CacheError::BadHeader {
+            path: self.path.clone(),
+            offset,
+            reason: reason.to_owned(),
+        }
+    }
+
+    fn overrun(&self, offset: usize) -> CacheError {
+        CacheError::DecompressionOverrun {
+            path: self.path.clone(),
+            offset,
+        }
+    }
+
+    fn missing_chunk(&self, index: usize) -> CacheError {
+        CacheError::MissingChunk {
+            path: self.path.clone(),
+            index,
+        }
+    }
+}
+
+/// Opens the data set of `variant` in `data_dir`, whose graphics chunks are laid out as
+/// `layout` says.
+pub fn init(
+    data_dir: &Path,
+    variant: GameVariant,
+    layout: ChunkLayout,
+) -> Result<Cache, CacheError> {
+    let graphics = GraphicsFile::open(data_dir, variant, layout)?;
+    let vswap = VswapFile::open(data_dir, variant)?;
+    let game_maps = GameMapsFile::open(data_dir, variant)?;
+    let audio = AudioFile::open(data_dir, variant)?;
+
+    Ok(Cache {
+        fonts: empty_cells(NUMFONT),
+        pics: empty_cells(graphics.pictable.len()),
+        tile8: OnceCell::new(),
+        text_screens: empty_cells(graphics.num_externs(ExternKind::TextScreen)),
+        articles: empty_cells(graphics.num_externs(ExternKind::Article)),
+        demos: empty_cells(graphics.num_externs(ExternKind::Demo)),
+        palettes: empty_cells(graphics.num_externs(ExternKind::Palette)),
+        textures: empty_cells(vswap.sprite_start),
+        sprites: empty_cells(vswap.sound_start - vswap.sprite_start),
+        sounds: empty_cells(vswap.digi_list.len()),
+        maps: empty_cells(game_maps.head.pointers.len()),
+        pc_sounds: empty_cells(audio.num_sounds),
+        adlib_sounds: empty_cells(audio.num_sounds),
+        music: empty_cells(audio.num_music()),
+        graphics,
+        vswap,
+        game_maps,
+        audio,
+        mod_dir: None,
+    })
+}
+
+/// VGAGRAPH along with the huffman dictionary and chunk offsets needed to decode it.
+struct GraphicsFile {
+    file: DataFile,
+    headers: Vec<u32>,
+    huff: Vec<(u16, u16)>,
+    /// Width and height of every picture, starting at `STARTPICS`.
+    pictable: Vec<(u16, u16)>,
+    layout: ChunkLayout,
+}
+
+impl GraphicsFile {
+    fn open(
+        data_dir: &Path,
+        variant: GameVariant,
+        layout: ChunkLayout,
+    ) -> Result<Self, CacheError> {
+        Self::open_files(
+            data_file(data_dir, "VGADICT", variant),
+            data_file(data_dir, "VGAHEAD", variant),
+            data_file(data_dir, "VGAGRAPH", variant),
+            layout,
+        )
     }
 
-    let headers_file = fs::read(DATADIR.to_owned() + "/VGAHEAD.WL1")
-        .expect("Something went wrong reading the file");
+    fn open_files(
+        dict: PathBuf,
+        head: PathBuf,
+        graph: PathBuf,
+        layout: ChunkLayout,
+    ) -> Result<Self, CacheError> {
+        let huff_file = DataFile::open(dict)?;
+        let huff_bytes = huff_file.read_all()?;
+        if huff_bytes.len() < HUFF_NODES * 4 {
+            return Err(huff_file.bad_header(0, "huffman dictionary is too short"));
+        }
+
+        let mut huff: Vec<(u16, u16)> = Vec::new();
+
+        for i in huff_bytes.chunks_exact(4) {
+            let bit0 = u16::from_le_bytes([i[0], i[1]]);
+            let bit1 = u16::from_le_bytes([i[2], i[3]]);
+            huff.push((bit0, bit1));
+        }
+
+        let headers_file = DataFile::open(head)?;
+
+        let mut buffer = [0u8; 4];
+        let mut headers: Vec<u32> = Vec::new();
+
+        for i in headers_file.read_all()?.chunks_exact(GRAPHICS_OFFSET_SIZE) {
+            buffer[..GRAPHICS_OFFSET_SIZE].copy_from_slice(i);
+            headers.push(u32::from_le_bytes(buffer));
+        }
 
-    let mut buffer = [0u8; 4];
-    let mut headers: Vec<u32> = Vec::new();
+        if headers.len() < layout.start_externs() + 1 {
+            return Err(headers_file.bad_header(0, "not enough graphics chunks"));
+        }
+
+        let file = DataFile::open(graph)?;
 
-    for i in headers_file.chunks_exact(3) {
-        buffer[..3].copy_from_slice(i);
-        headers.push(u32::from_le_bytes(buffer));
+        // the picture table is the first chunk, its length tells how many pictures there are
+        if file.read_u32(0)? as usize != layout.num_pics * 4 {
+            return Err(file.bad_header(0, "picture table doesn't match the chunk layout"));
+        }
+        let a = headers[1] as usize;
+        let pictable_source = file.read(4, a.saturating_sub(4))?;
+        let pictable_bytes = huff_expand(&huff, &pictable_source, layout.num_pics * 4)
+            .ok_or_else(|| file.overrun(0))?;
+        let pictable = pictable_bytes
+            .chunks_exact(4)
+            .map(|i| {
+                (
+                    u16::from_le_bytes([i[0], i[1]]),
+                    u16::from_le_bytes([i[2], i[3]]),
+                )
+            })
+            .collect();
+
+        Ok(GraphicsFile {
+            file,
+            headers,
+            huff,
+            pictable,
+            layout,
+        })
     }
 
-    let graph_file = fs::read(DATADIR.to_owned() + "/VGAGRAPH.WL1")
-        .expect("Something went wrong reading the file");
+    /// Number of chunks of a kind after the 8x8 tiles, of those in the file. VGAHEAD has
+    /// one more offset than there are chunks, where the last one ends.
+    fn num_externs(&self, kind: ExternKind) -> usize {
+        let in_file = self
+            .headers
+            .len()
+            .saturating_sub(self.layout.start_externs() + 1);
+        self.layout
+            .externs
+            .iter()
+            .take(in_file)
+            .filter(|&&k| k == kind)
+            .count()
+    }
 
-    let a = headers[1] as usize;
-    let pictable_bytes = huff_expand(&huff, &graph_file[4..a], (NUMPICS + 3) * 4);
+    fn load_tile8(&self) -> Result<Vec<Picture>, CacheError> {
+        let data = self.load_chunk(self.layout.start_tile8())?;
+        Ok(data
+            .chunks_exact(TILE8_SIZE * TILE8_SIZE)
+            .map(|tile| Picture {
+                width: TILE8_SIZE as u32,
+                height: TILE8_SIZE as u32,
+                data: tile.to_vec(),
+            })
+            .collect())
+    }
 
-    let mut pics: Vec<Picture> = Vec::new();
+    fn load_font(&self, chunk: usize) -> Result<Font, CacheError> {
+        let data = self.load_chunk(chunk)?;
+        parse_font(data).ok_or_else(|| {
+            self.file
+                .bad_header(self.headers[chunk] as usize, "bad font")
+        })
+    }
 
-    for chunk in STARTPICS..GETPSYCHEDPIC + 1 {
-        let i = (chunk - STARTPICS) * 4;
-        let width = u16::from_le_bytes([pictable_bytes[i], pictable_bytes[i + 1]]);
-        let height = u16::from_le_bytes([pictable_bytes[i + 2], pictable_bytes[i + 3]]);
-        let data = load_graphic(&graph_file, &headers, &huff, chunk);
+    fn load_pic(&self, chunk: usize) -> Result<Picture, CacheError> {
+        let (width, height) = self.pictable[chunk - STARTPICS];
+        let data = self.load_chunk(chunk)?;
 
-        pics.push(Picture {
+        Ok(Picture {
             width: width as u32,
             height: height as u32,
             data,
         })
     }
 
-    let vswap_file =
-        fs::read(DATADIR.to_owned() + "/VSWAP.WL1").expect("Something went wrong reading the file");
+    fn load_chunk(&self, chunk: usize) -> Result<Vec<u8>, CacheError> {
+        let source = &self.file;
+        let (pos, end) = match (self.headers.get(chunk), self.headers.get(chunk + 1)) {
+            (Some(&pos), Some(&end)) => (pos as usize, end as usize),
+            _ => return Err(source.missing_chunk(chunk)),
+        };
+
+        // the 8x8 tiles don't start with their length, all of them are in one chunk
+        let (length, start) = if chunk == self.layout.start_tile8() {
+            (TILE8_SIZE * TILE8_SIZE * NUMTILE8, pos)
+        } else {
+            if end < pos + 4 {
+                return Err(source.truncated(pos, end.saturating_sub(pos)));
+            }
+            (source.read_u32(pos)? as usize, pos + 4)
+        };
+        if end < start {
+            return Err(source.truncated(pos, 0));
+        }
+        let compressed = source.read(start, end - start)?;
+        huff_expand(&self.huff, &compressed, length).ok_or_else(|| source.overrun(pos))
+    }
+}
 
-    let chunks_in_file = u16::from_le_bytes([vswap_file[0], vswap_file[1]]) as usize;
-    let pm_sprite_start = u16::from_le_bytes([vswap_file[2], vswap_file[3]]) as usize;
-    let pm_sound_start = u16::from_le_bytes([vswap_file[4], vswap_file[5]]) as usize;
-    let mut page_offsets: Vec<u32> = Vec::new();
-    let mut page_lengths: Vec<u16> = Vec::new();
-    let offsets_start = 6;
-    let offsets_end = offsets_start + 4 * (chunks_in_file + 1);
-    let lengths_start = offsets_end;
-    let lengths_end = lengths_start + 2 * chunks_in_file;
+/// VSWAP page table, which holds the offsets of textures, sprites and digitized sounds.
+struct VswapFile {
+    file: DataFile,
+    /// Offset and length of every page, sparse pages have offset 0.
+    pages: Vec<(u32, u16)>,
+    sprite_start: usize,
+    sound_start: usize,
+    digi_list: Vec<DigiEntry>,
+}
 
-    for i in vswap_file[offsets_start..offsets_end].chunks_exact(4) {
-        let offset = u32::from_le_bytes(i.try_into().unwrap());
-        page_offsets.push(offset);
+/// Where a digitized sound is stored: the pages it spans and its length in bytes,
+/// as the last page may be partly used.
+#[derive(Debug, PartialEq, Eq)]
+struct DigiEntry {
+    start_page: usize,
+    end_page: usize,
+    length: usize,
+}
+
+impl VswapFile {
+    fn open(data_dir: &Path, variant: GameVariant) -> Result<Self, CacheError> {
+        let file = DataFile::open(data_file(data_dir, "VSWAP", variant))?;
+
+        let chunks_in_file = file.read_u16(0)? as usize;
+        let pm_sprite_start = file.read_u16(2)? as usize;
+        let pm_sound_start = file.read_u16(4)? as usize;
+        if pm_sprite_start > pm_sound_start || pm_sound_start > chunks_in_file {
+            return Err(file.bad_header(0, "sprite and sound pages are out of order"));
+        }
+
+        let offsets_start = 6;
+        let offsets_end = offsets_start + 4 * chunks_in_file;
+        let lengths_start = offsets_end;
+        let lengths_end = lengths_start + 2 * chunks_in_file;
+
+        let page_offsets = file.read(offsets_start, offsets_end - offsets_start)?;
+        let page_lengths = file.read(lengths_start, lengths_end - lengths_start)?;
+        let pages = page_offsets
+            .chunks_exact(4)
+            .zip(page_lengths.chunks_exact(2))
+            .map(|(offset, length)| {
+                (
+                    u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]),
+                    u16::from_le_bytes([length[0], length[1]]),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // the last page is not a sound but the list of digitized sounds
+        let digi_list = if pm_sound_start < chunks_in_file {
+            let info_page = pages[chunks_in_file - 1];
+            let info = file.read(info_page.0 as usize, info_page.1 as usize)?;
+            parse_digi_list(&info, &pages, pm_sound_start)
+        } else {
+            Vec::new()
+        };
+
+        Ok(VswapFile {
+            file,
+            pages,
+            sprite_start: pm_sprite_start,
+            sound_start: pm_sound_start,
+            digi_list,
+        })
     }
 
-    for i in vswap_file[lengths_start..lengths_end].chunks_exact(2) {
-        let length = u16::from_le_bytes(i.try_into().unwrap());
-        page_lengths.push(length);
+    fn page(&self, index: usize) -> Result<Vec<u8>, CacheError> {
+        match self.pages.get(index) {
+            Some(&(offset, length)) if offset != 0 => {
+                self.file.read(offset as usize, length as usize)
+            }
+            // sparse page
+            _ => Err(self.file.missing_chunk(index)),
+        }
     }
 
-    let mut textures: Vec<Vec<u8>> = Vec::new();
-    let mut sprites: Vec<(CompShape, Vec<u8>)> = Vec::new();
-    let mut sounds: Vec<Vec<u8>> = Vec::new();
+    /// Reads a wall texture page, which must hold all 64x64 pixels.
+    fn load_texture(&self, index: usize) -> Result<Vec<u8>, CacheError> {
+        let texture = self.page(index)?;
+        let length = WALLPIC_WIDTH * WALLPIC_HEIGHT;
+        if texture.len() < length {
+            return Err(self.file.truncated(self.pages[index].0 as usize, length));
+        }
+        Ok(texture)
+    }
 
-    for i in 0..chunks_in_file - 1 {
-        // last value fails as it seems length is wrong
-        if page_offsets[i] == 0 {
-            // sparse page
-            continue;
-        }
-
-        let mut value_end = page_offsets[i + 1] as usize;
-        if page_offsets[i + 1] == 0 {
-            value_end = page_offsets[i] as usize + page_lengths[i] as usize;
-        }
-
-        let value = &vswap_file[page_offsets[i] as usize..value_end];
-        if i < pm_sprite_start {
-            textures.push(value.to_vec());
-        } else if i < pm_sound_start {
-            // for sprites we parse the CompShape struct as well
-            if !value.is_empty() {
-                sprites.push((
-                    CompShape {
-                        left_pix: u16::from_le_bytes([value[0], value[1]]),
-                        right_pix: u16::from_le_bytes([value[2], value[3]]),
-                        dataofs: value[4..132]
-                            .chunks_exact(2)
-                            .into_iter()
-                            .map(|a| u16::from_le_bytes([a[0], a[1]]))
-                            .collect(),
-                    },
-                    value.to_vec(),
-                ));
+    fn load_sprite(&self, index: usize) -> Result<Sprite, CacheError> {
+        let value = self.page(index)?;
+        parse_sprite(&value).ok_or_else(|| {
+            self.file
+                .bad_header(self.pages[index].0 as usize, "bad sprite")
+        })
+    }
+
+    fn load_sound(&self, index: usize) -> Result<DigiSound, CacheError> {
+        let entry = &self.digi_list[index];
+        let mut samples = Vec::with_capacity(entry.length);
+        for page in entry.start_page..entry.end_page {
+            samples.extend(self.page(page)?);
+        }
+        if samples.len() < entry.length {
+            let offset = self.pages[entry.start_page].0 as usize;
+            return Err(self.file.truncated(offset, entry.length));
+        }
+        samples.truncate(entry.length);
+        Ok(DigiSound { samples })
+    }
+}
+
+/// Parses the sound info page, made of (first page, length) word pairs, one per sound.
+/// A sound spans every page up to the first page of the next one. Only the low 16 bits
+/// of the length fit in the info page, the rest is worked out from the page sizes.
+/// The list ends at a sound starting before the one ahead of it, which then spans every
+/// page up to the info page.
+fn parse_digi_list(info: &[u8], pages: &[(u32, u16)], sound_start: usize) -> Vec<DigiEntry> {
+    let info_page = pages.len() - 1;
+    let words: Vec<usize> = info
+        .chunks_exact(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]) as usize)
+        .collect();
+    let count = words.len() / 2;
+
+    let mut list = Vec::new();
+    for i in 0..count {
+        let start_page = sound_start + words[i * 2];
+        if start_page >= info_page {
+            break;
+        }
+
+        let next_page = words.get(i * 2 + 2).map(|&next| sound_start + next);
+        let backwards = next_page.is_some_and(|next| next != sound_start && next < start_page);
+        let end_page = match next_page {
+            Some(next) if next != sound_start && next <= info_page && !backwards => next,
+            _ => info_page,
+        };
+        let size: usize = pages[start_page..end_page]
+            .iter()
+            .map(|&(_, length)| length as usize)
+            .sum();
+
+        // page sizes include some padding, so only keep the high bits from them
+        let low_bits = words[i * 2 + 1];
+        let mut length = size;
+        if length & !0xFFFF != 0 && (length & 0xFFFF) < low_bits {
+            length -= 0x10000;
+        }
+        length = (length & !0xFFFF) | low_bits;
+
+        list.push(DigiEntry {
+            start_page,
+            end_page,
+            length,
+        });
+        if backwards {
+            break;
+        }
+    }
+    list
+}
+
+/// Reads the header of a font chunk: height, then the location and width of every glyph.
+fn parse_font(data: Vec<u8>) -> Option<Font> {
+    const GLYPHS: usize = 256;
+    let height = u16::from_le_bytes([*data.first()?, *data.get(1)?]);
+    let location = data
+        .get(2..2 + GLYPHS * 2)?
+        .chunks_exact(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]))
+        .collect();
+    let width = data.get(2 + GLYPHS * 2..2 + GLYPHS * 3)?.to_vec();
+    Some(Font {
+        height,
+        location,
+        width,
+        data,
+    })
+}
+
+fn parse_text_screen(data: Vec<u8>) -> Option<TextScreen> {
+    let cells = data
+        .get(BSAVE_HEADER_SIZE..)?
+        .chunks_exact(2)
+        .map(|cell| (cell[0], cell[1]))
+        .collect();
+    Some(TextScreen { cells })
+}
+
+/// Splits an article into pages at each `^P`, up to the `^E` that ends it.
+fn parse_article(data: &[u8]) -> Article {
+    // the text is plain ASCII, with DOS line endings
+    let text: String = data.iter().map(|&b| b as char).collect();
+    let text = text.replace("\r\n", "\n");
+    let text = match text.find("^E") {
+        Some(end) => &text[..end],
+        None => &text,
+    };
+    let pages = text
+        .split("^P")
+        .skip(1)
+        .map(|page| page.trim_start_matches('\n').to_string())
+        .collect();
+    Article { pages }
+}
+
+/// Reads a demo: the map number, a word with the size of the demo (counting these
+/// first 4 bytes), an unused byte and then 3 bytes per tic.
+fn parse_demo(data: Vec<u8>) -> Option<Demo> {
+    let map = *data.first()?;
+    let length = u16::from_le_bytes([*data.get(1)?, *data.get(2)?]) as usize;
+    let commands = data
+        .get(4..length.min(data.len()))?
+        .chunks_exact(3)
+        .map(|c| DemoCommand {
+            buttons: c[0],
+            dx: c[1] as i8,
+            dy: c[2] as i8,
+        })
+        .collect();
+    Some(Demo { map, commands })
+}
+
+fn parse_palette(data: Vec<u8>) -> Option<Palette> {
+    let colors = data
+        .get(..PALETTE_SIZE)?
+        .chunks_exact(3)
+        .map(|c| (c[0], c[1], c[2]))
+        .collect();
+    Some(Palette { colors })
+}
+
+/// Decodes a sprite page. The commands of each column are a list of posts, made of 3 words:
+/// the row where the post ends times 2, the offset in the page of the pixels minus the row
+/// where the post starts (so the pixel of row y is at that offset + y) and the row where
+/// the post starts times 2. The list ends with a 0 word.
+fn parse_sprite(value: &[u8]) -> Option<Sprite> {
+    let shape = parse_comp_shape(value)?;
+    let read_word = |offset: usize| {
+        value
+            .get(offset..offset + 2)
+            .map(|w| u16::from_le_bytes([w[0], w[1]]))
+    };
+
+    let mut columns = Vec::with_capacity(shape.dataofs.len());
+    for &commands in &shape.dataofs {
+        let mut spans = Vec::new();
+        let mut post = commands as usize;
+        loop {
+            let end = (read_word(post)? >> 1) as usize;
+            if end == 0 {
+                break;
             }
-        } else {
-            sounds.push(value.to_vec());
+            let source = read_word(post + 2)? as i16 as isize;
+            let start = (read_word(post + 4)? >> 1) as usize;
+            if start >= end || end > SPRITE_WIDTH {
+                return None;
+            }
+            let first = usize::try_from(source + start as isize).ok()?;
+            let pixels = value.get(first..first + end - start)?.to_vec();
+            spans.push(Span {
+                start: start as u16,
+                pixels,
+            });
+            post += 6;
+        }
+        columns.push(spans);
+    }
+
+    Some(Sprite {
+        left_pix: shape.left_pix,
+        right_pix: shape.right_pix,
+        columns,
+    })
+}
+
+/// The inverse of `parse_sprite`: the header, then the pixels of every span, then the
+/// posts of each column, pointing back at their pixels.
+pub fn encode_sprite(sprite: &Sprite) -> Vec<u8> {
+    let header_size = 4 + 2 * sprite.columns.len();
+    let pixel_count: usize = sprite
+        .columns
+        .iter()
+        .flatten()
+        .map(|span| span.pixels.len())
+        .sum();
+    let posts_start = header_size + pixel_count;
+
+    let mut pixels: Vec<u8> = Vec::with_capacity(pixel_count);
+    let mut posts = Vec::new();
+    let mut shape = CompShape {
+        left_pix: sprite.left_pix,
+        right_pix: sprite.right_pix,
+        dataofs: Vec::with_capacity(sprite.columns.len()),
+    };
+    for spans in &sprite.columns {
+        shape.dataofs.push((posts_start + posts.len()) as u16);
+        for span in spans {
+            let end = span.start as usize + span.pixels.len();
+            // the post's pixel offset is where the pixel of row 0 would be
+            let source = (header_size + pixels.len()) as isize - span.start as isize;
+            posts.extend(((end * 2) as u16).to_le_bytes());
+            posts.extend((source as i16).to_le_bytes());
+            posts.extend((span.start * 2).to_le_bytes());
+            pixels.extend(&span.pixels);
+        }
+        posts.extend(0u16.to_le_bytes());
+    }
+
+    let mut page = Vec::with_capacity(posts_start + posts.len());
+    page.extend(shape.left_pix.to_le_bytes());
+    page.extend(shape.right_pix.to_le_bytes());
+    for offset in shape.dataofs {
+        page.extend(offset.to_le_bytes());
+    }
+    page.extend(pixels);
+    page.extend(posts);
+    page
+}
+
+/// Reads the header of a sprite page, if its columns are within bounds.
+fn parse_comp_shape(value: &[u8]) -> Option<CompShape> {
+    let left_pix = u16::from_le_bytes([*value.first()?, *value.get(1)?]);
+    let right_pix = u16::from_le_bytes([*value.get(2)?, *value.get(3)?]);
+    if left_pix > right_pix || right_pix as usize >= SPRITE_WIDTH {
+        return None;
+    }
+
+    let columns = (right_pix - left_pix + 1) as usize;
+    Some(CompShape {
+        left_pix,
+        right_pix,
+        dataofs: value
+            .get(4..4 + columns * 2)?
+            .chunks_exact(2)
+            .map(|a| u16::from_le_bytes([a[0], a[1]]))
+            .collect(),
+    })
+}
+
+/// AUDIOT along with the chunk offsets read from AUDIOHED. Chunks are laid out as
+/// `num_sounds` PC speaker sounds, the same number of AdLib sounds and digitized sounds
+/// (unused, those come from VSWAP), and then the music tracks.
+struct AudioFile {
+    file: DataFile,
+    offsets: Vec<u32>,
+    num_sounds: usize,
+}
+
+impl AudioFile {
+    fn open(data_dir: &Path, variant: GameVariant) -> Result<Self, CacheError> {
+        let head_file = DataFile::open(data_file(data_dir, "AUDIOHED", variant))?;
+        let offsets: Vec<u32> = head_file
+            .read_all()?
+            .chunks_exact(4)
+            .map(|i| u32::from_le_bytes([i[0], i[1], i[2], i[3]]))
+            .collect();
+
+        let num_sounds = variant.num_sounds();
+        if offsets.len() < 3 * num_sounds + 1 {
+            return Err(head_file.bad_header(0, "not enough sound chunks"));
         }
+
+        let file = DataFile::open(data_file(data_dir, "AUDIOT", variant))?;
+        Ok(AudioFile {
+            file,
+            offsets,
+            num_sounds,
+        })
+    }
+
+    fn num_music(&self) -> usize {
+        self.offsets.len() - 1 - 3 * self.num_sounds
+    }
+
+    fn chunk(&self, index: usize) -> Result<Vec<u8>, CacheError> {
+        let start = self.offsets[index] as usize;
+        let end = self.offsets[index + 1] as usize;
+        if end <= start {
+            return Err(self.file.missing_chunk(index));
+        }
+        self.file.read(start, end - start)
+    }
+
+    fn load_pc_sound(&self, index: usize) -> Result<PcSound, CacheError> {
+        let chunk = self.chunk(index)?;
+        parse_pc_sound(&chunk).ok_or_else(|| self.bad_chunk(index))
+    }
+
+    fn load_adlib_sound(&self, index: usize) -> Result<AdlibSound, CacheError> {
+        let chunk = self.chunk(self.num_sounds + index)?;
+        parse_adlib_sound(&chunk).ok_or_else(|| self.bad_chunk(self.num_sounds + index))
+    }
+
+    fn load_music(&self, index: usize) -> Result<ImfSong, CacheError> {
+        let chunk = self.chunk(3 * self.num_sounds + index)?;
+        parse_imf_song(&chunk).ok_or_else(|| self.bad_chunk(3 * self.num_sounds + index))
     }
 
-    let maps = load_maps();
+    fn bad_chunk(&self, index: usize) -> CacheError {
+        let length = self.offsets[index + 1] - self.offsets[index];
+        self.file
+            .truncated(self.offsets[index] as usize, length as usize)
+    }
+}
 
-    Cache::new(pics, textures, sprites, sounds, maps)
+/// Sound effects start with a common header: the length of the sound data and its
+/// priority over other sounds.
+fn parse_sound_common(chunk: &[u8]) -> Option<(usize, u16)> {
+    let header = chunk.get(0..6)?;
+    let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let priority = u16::from_le_bytes([header[4], header[5]]);
+    Some((length as usize, priority))
 }
 
-fn huff_expand(huff: &[(u16, u16)], source: &[u8], length: usize) -> Vec<u8> {
+fn parse_pc_sound(chunk: &[u8]) -> Option<PcSound> {
+    let (length, priority) = parse_sound_common(chunk)?;
+    Some(PcSound {
+        priority,
+        data: chunk.get(6..6 + length)?.to_vec(),
+    })
+}
+
+fn parse_adlib_sound(chunk: &[u8]) -> Option<AdlibSound> {
+    let (length, priority) = parse_sound_common(chunk)?;
+    // 13 instrument bytes, 3 of padding and then the octave
+    let inst = chunk.get(6..22)?;
+    let block = *chunk.get(22)?;
+    Some(AdlibSound {
+        priority,
+        instrument: Instrument {
+            m_char: inst[0],
+            c_char: inst[1],
+            m_scale: inst[2],
+            c_scale: inst[3],
+            m_attack: inst[4],
+            c_attack: inst[5],
+            m_sus: inst[6],
+            c_sus: inst[7],
+            m_wave: inst[8],
+            c_wave: inst[9],
+            n_conn: inst[10],
+            voice: inst[11],
+            mode: inst[12],
+        },
+        block,
+        data: chunk.get(23..23 + length)?.to_vec(),
+    })
+}
+
+/// Music chunks start with the length in bytes of the IMF commands that follow.
+fn parse_imf_song(chunk: &[u8]) -> Option<ImfSong> {
+    let length = u16::from_le_bytes([*chunk.first()?, *chunk.get(1)?]) as usize;
+    let commands = chunk
+        .get(2..2 + length)?
+        .chunks_exact(4)
+        .map(|c| ImfCommand {
+            register: c[0],
+            value: c[1],
+            delay: u16::from_le_bytes([c[2], c[3]]),
+        })
+        .collect();
+    Some(ImfSong { commands })
+}
+
+/// Expands `length` bytes out of `source`, or returns None if the huffman tree or the
+/// source bytes run out before that.
+fn huff_expand(huff: &[(u16, u16)], source: &[u8], length: usize) -> Option<Vec<u8>> {
     let mut dest: Vec<u8> = Vec::new();
     let headptr = 254; // head node is always node 254
     let mut huffptr = headptr;
@@ -336,11 +1796,12 @@ fn huff_expand(huff: &[(u16, u16)], source: &[u8], length: usize) -> Vec<u8> {
     let mut mask = 1;
     let mut nodeval;
 
-    loop {
-        if (source[i] & mask) == 0 {
-            nodeval = huff[huffptr].0;
+    while dest.len() < length {
+        let node = huff.get(huffptr)?;
+        if (source.get(i)? & mask) == 0 {
+            nodeval = node.0;
         } else {
-            nodeval = huff[huffptr].1;
+            nodeval = node.1;
         }
         if mask == 0x80 {
             i += 1;
@@ -349,24 +1810,93 @@ fn huff_expand(huff: &[(u16, u16)], source: &[u8], length: usize) -> Vec<u8> {
             mask <<= 1;
         }
         if nodeval < 256 {
-            dest.push(nodeval.try_into().unwrap());
+            dest.push(nodeval as u8);
             huffptr = headptr;
-            if dest.len() >= length {
-                break;
-            }
         } else {
             huffptr = nodeval as usize - 256;
         }
     }
-    dest
+    Some(dest)
 }
 
-fn load_graphic(source: &[u8], headers: &[u32], huff: &[(u16, u16)], chunk: usize) -> Vec<u8> {
-    let pos = headers[chunk] as usize;
-    let end = headers[chunk + 1] as usize;
+/// Reads every chunk of a VGADICT, VGAHEAD and VGAGRAPH set, expanded, e.g. to edit some
+/// of them and write them back.
+pub fn read_graphics(
+    dict: &Path,
+    head: &Path,
+    graph: &Path,
+    layout: ChunkLayout,
+) -> Result<Vec<Vec<u8>>, CacheError> {
+    let file = GraphicsFile::open_files(
+        dict.to_path_buf(),
+        head.to_path_buf(),
+        graph.to_path_buf(),
+        layout,
+    )?;
+    (0..file.headers.len() - 1)
+        .map(|chunk| file.load_chunk(chunk))
+        .collect()
+}
+
+/// Builds the huffman dictionary that codes `chunks` in the fewest bits. Every byte gets
+/// a leaf, even the ones that aren't used, so there are always `HUFF_NODES` nodes, the
+/// head being the last one, as `huff_expand` expects.
+pub fn huff_dictionary<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> Vec<(u16, u16)> {
+    let mut counts = [0u64; 256];
+    for chunk in chunks {
+        for &byte in chunk {
+            counts[byte as usize] += 1;
+        }
+    }
 
-    let length = u32::from_le_bytes(source[pos..pos + 4].try_into().unwrap());
-    huff_expand(huff, &source[pos + 4..end], length.try_into().unwrap())
+    // node values below 256 are bytes, the rest point at node value - 256; ties are broken
+    // by value so the same data always gets the same dictionary
+    let mut queue: BinaryHeap<Reverse<(u64, u16)>> = counts
+        .iter()
+        .enumerate()
+        .map(|(byte, &count)| Reverse((count, byte as u16)))
+        .collect();
+    let mut nodes = Vec::with_capacity(HUFF_NODES);
+    while let (Some(Reverse((count0, bit0))), Some(Reverse((count1, bit1)))) =
+        (queue.pop(), queue.pop())
+    {
+        nodes.push((bit0, bit1));
+        queue.push(Reverse((count0 + count1, 256 + nodes.len() as u16 - 1)));
+    }
+    nodes
+}
+
+/// The inverse of `huff_expand`: the code of each byte, bits going from the lowest to
+/// the highest of each output byte.
+pub fn huff_compress(huff: &[(u16, u16)], data: &[u8]) -> Vec<u8> {
+    let mut codes = vec![Vec::new(); 256];
+    let mut pending = vec![(HUFF_NODES - 1, Vec::new())];
+    while let Some((node, code)) = pending.pop() {
+        for (bit, value) in [(false, huff[node].0), (true, huff[node].1)] {
+            let mut code = code.clone();
+            code.push(bit);
+            if value < 256 {
+                codes[value as usize] = code;
+            } else {
+                pending.push((value as usize - 256, code));
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    let mut bits = 0;
+    for &byte in data {
+        for &bit in &codes[byte as usize] {
+            if bits % 8 == 0 {
+                output.push(0);
+            }
+            if bit {
+                *output.last_mut().unwrap() |= 1 << (bits % 8);
+            }
+            bits += 1;
+        }
+    }
+    output
 }
 
 // see some map plans here: https://wolfenstein.fandom.com/wiki/Wolfenstein_3D
@@ -383,24 +1913,85 @@ struct MapHead {
     title: Vec<u8>, // so far empty in map files we've seen, no idea what to do with this for now... could probably be a string
 }
 
-fn load_maps() -> Vec<Map> {
-    let map_metadata = parse_map_head(DATADIR.to_owned() + "/MAPHEAD.WL1");
-    parse_map_data(DATADIR.to_owned() + "/GAMEMAPS.WL1", map_metadata)
+/// GAMEMAPS along with the level offsets read from MAPHEAD.
+struct GameMapsFile {
+    file: DataFile,
+    head: MapHead,
 }
 
-fn parse_map_head<P: AsRef<Path>>(path: P) -> MapHead {
-    let raw_data = fs::read(path).expect("could not read MAPHEAD file");
+impl GameMapsFile {
+    fn open(data_dir: &Path, variant: GameVariant) -> Result<Self, CacheError> {
+        Self::open_files(
+            data_file(data_dir, "MAPHEAD", variant),
+            data_file(data_dir, "GAMEMAPS", variant),
+        )
+    }
+
+    fn open_files(map_head: PathBuf, game_maps: PathBuf) -> Result<Self, CacheError> {
+        let head = parse_map_head(map_head)?;
+        let file = DataFile::open(game_maps)?;
+        Ok(GameMapsFile { file, head })
+    }
+
+    fn load_map(&self, index: usize) -> Result<Map, CacheError> {
+        // levels that are not in the data set have no header
+        let pointer = self.head.pointers[index];
+        if pointer <= 0 {
+            return Err(self.file.missing_chunk(index));
+        }
+        let pointer = pointer as usize;
+        let header = MapLevelHeader::new(&self.file.read(pointer, MAP_LEVEL_HEADER_SIZE)?);
 
-    MapHead {
-        magic: raw_data[0..2].try_into().unwrap(),
-        pointers: raw_data[2..=(4 * 100)]
+        if header.offset_plane0 == 0 {
+            return Err(self.file.missing_chunk(index));
+        }
+
+        let width = header.width_n_tiles as usize;
+        let height = header.height_n_tiles as usize;
+        let plane = |offset, length| {
+            get_plane(&self.file, offset, length, &self.head.magic, width * height)
+        };
+        let map = Map::new(
+            width,
+            height,
+            plane(header.offset_plane0, header.length_plane0)?,
+            plane(header.offset_plane1, header.length_plane1)?,
+            header.name,
+        );
+        // plane2 is unused in wolf, but mods may store things there
+        if header.length_plane2 == 0 {
+            return Ok(map);
+        }
+        Ok(map.with_plane2(plane(header.offset_plane2, header.length_plane2)?))
+    }
+}
+
+/// Reads every level of a MAPHEAD and GAMEMAPS pair, e.g. to edit them without the rest
+/// of the data set.
+pub fn read_maps(map_head: &Path, game_maps: &Path) -> Result<Vec<Map>, CacheError> {
+    let file = GameMapsFile::open_files(map_head.to_path_buf(), game_maps.to_path_buf())?;
+    (0..file.head.pointers.len())
+        .map(|index| file.load_map(index))
+        .collect()
+}
+
+fn parse_map_head(path: PathBuf) -> Result<MapHead, CacheError> {
+    let file = DataFile::open(path)?;
+    let raw_data = file.read_all()?;
+    if raw_data.len() < 2 + 4 * 100 {
+        return Err(file.truncated(0, 2 + 4 * 100));
+    }
+
+    Ok(MapHead {
+        magic: [raw_data[0], raw_data[1]],
+        pointers: raw_data[2..(2 + 4 * 100)]
             .chunks_exact(4)
             .take(NUM_MAPS)
-            .map(|x| i32::from_le_bytes(x.try_into().unwrap()))
+            .map(|x| i32::from_le_bytes([x[0], x[1], x[2], x[3]]))
             .filter(|&x| x > 0)
             .collect(),
         title: raw_data[(2 + (4 * 100))..].to_owned(),
-    }
+    })
 }
 
 #[derive(Debug)]
@@ -416,29 +2007,41 @@ struct MapLevelHeader {
     name: String,
 }
 
+pub const MAP_LEVEL_HEADER_SIZE: usize = 38;
+
 impl MapLevelHeader {
     pub fn new(header_data: &[u8]) -> Self {
-        assert_eq!(header_data.len(), 38);
+        let read_i32 = |i: usize| {
+            i32::from_le_bytes([
+                header_data[i],
+                header_data[i + 1],
+                header_data[i + 2],
+                header_data[i + 3],
+            ])
+        };
+        let read_u16 = |i: usize| u16::from_le_bytes([header_data[i], header_data[i + 1]]);
+        // the name is NUL terminated, whatever comes after that is garbage
+        let name = header_data[22..MAP_LEVEL_HEADER_SIZE]
+            .split(|&c| c == 0)
+            .next()
+            .unwrap_or_default();
+
         MapLevelHeader {
-            // TODO can this be simplified by reading the entire struct based on its C types?
-            offset_plane0: i32::from_le_bytes(header_data[0..4].try_into().unwrap()),
-            offset_plane1: i32::from_le_bytes(header_data[4..8].try_into().unwrap()),
-            offset_plane2: i32::from_le_bytes(header_data[8..12].try_into().unwrap()),
-            length_plane0: u16::from_le_bytes(header_data[12..14].try_into().unwrap()),
-            length_plane1: u16::from_le_bytes(header_data[14..16].try_into().unwrap()),
-            length_plane2: u16::from_le_bytes(header_data[16..18].try_into().unwrap()),
-            width_n_tiles: u16::from_le_bytes(header_data[18..20].try_into().unwrap()),
-            height_n_tiles: u16::from_le_bytes(header_data[20..22].try_into().unwrap()),
-            name: String::from_utf8(header_data[22..].to_owned())
-                .expect("map level header name could not be parsed")
-                .trim_end_matches(char::from(0))
-                .to_owned(),
+            offset_plane0: read_i32(0),
+            offset_plane1: read_i32(4),
+            offset_plane2: read_i32(8),
+            length_plane0: read_u16(12),
+            length_plane1: read_u16(14),
+            length_plane2: read_u16(16),
+            width_n_tiles: read_u16(18),
+            height_n_tiles: read_u16(20),
+            name: String::from_utf8_lossy(name).into_owned(),
         }
     }
 }
 
 /// See: https://moddingwiki.shikadi.net/wiki/Id_Software_RLEW_compression
-fn rlew_decompress(compressed_data: &[u8], magic_word: &[u8; 2]) -> Vec<u8> {
+fn rlew_decompress(compressed_data: &[u8], magic_word: &[u8; 2]) -> Option<Vec<u8>> {
     let mut output = Vec::new();
     let mut word_i = 0;
     let n_words_max = compressed_data.len() / 2;
@@ -447,37 +2050,31 @@ fn rlew_decompress(compressed_data: &[u8], magic_word: &[u8; 2]) -> Vec<u8> {
         let offset = word_i * 2;
         let word_bytes = &compressed_data[offset..(offset + 2)];
         if word_bytes == magic_word {
-            if word_i + 1 == n_words_max {
-                dbg!("malformed input?");
-                break;
-            }
-            let count = u16::from_le_bytes(
-                compressed_data[(offset + 2)..(offset + 4)]
-                    .try_into()
-                    .unwrap(),
-            ) as usize;
-            let value = &compressed_data[(offset + 4)..(offset + 6)];
+            let count = compressed_data.get((offset + 2)..(offset + 4))?;
+            let count = u16::from_le_bytes([count[0], count[1]]) as usize;
+            let value = compressed_data.get((offset + 4)..(offset + 6))?;
             output.extend(vec![value; count].concat());
             word_i += 3;
         } else {
-            output.extend_from_slice(&compressed_data[offset..(offset + 2)]);
+            output.extend_from_slice(word_bytes);
             word_i += 1;
         }
     }
-    // TODO: remove/revisit this ugly and inefficient hack for testing...
-    output.into_iter().take(64 * 64 * 2).collect()
+    Some(output)
 }
 
 /// See: https://moddingwiki.shikadi.net/wiki/Carmack_compression
-fn carmack_decompress(compressed_data: &[u8]) -> Vec<u8> {
+/// The data starts with its decompressed length, which is skipped rather than read as
+/// a word, as it may well look like a pointer.
+fn carmack_decompress(compressed_data: &[u8]) -> Option<Vec<u8>> {
     const NEAR_POINTER: u8 = 0xA7;
     const FAR_POINTER: u8 = 0xA8;
     let mut output = Vec::new();
-    let mut word_i = 0;
+    let mut word_i = 1;
     let mut n_shifts = 0;
-    let mut offset = 0;
+    let mut offset = 2;
 
-    while offset < compressed_data.len() - 2 {
+    while offset + 2 < compressed_data.len() {
         match &compressed_data[offset..(offset + 2)] {
             [0x00, NEAR_POINTER] | [0x00, FAR_POINTER] => {
                 // ignore 0x00 and invert the following word
@@ -487,21 +2084,18 @@ fn carmack_decompress(compressed_data: &[u8]) -> Vec<u8> {
             }
             [count, NEAR_POINTER] => {
                 let distance = usize::from(compressed_data[offset + 2]);
-                let segment_start = output.len() - distance * 2;
+                let segment_start = output.len().checked_sub(distance * 2)?;
                 let segment_end = segment_start + usize::from(*count) * 2;
-                let segment_to_repeat = output[segment_start..segment_end].to_vec();
+                let segment_to_repeat = output.get(segment_start..segment_end)?.to_vec();
                 output.extend_from_slice(&segment_to_repeat);
                 n_shifts += 1;
             }
             [count, FAR_POINTER] => {
-                let distance = u16::from_le_bytes(
-                    compressed_data[(offset + 2)..(offset + 4)]
-                        .try_into()
-                        .unwrap(),
-                );
-                let segment_start = (usize::from(distance) + 1) * 2;
+                let distance = compressed_data.get((offset + 2)..(offset + 4))?;
+                let distance = u16::from_le_bytes([distance[0], distance[1]]);
+                let segment_start = usize::from(distance) * 2;
                 let segment_end = segment_start + usize::from(*count) * 2;
-                let segment_to_repeat = output[segment_start..segment_end].to_vec();
+                let segment_to_repeat = output.get(segment_start..segment_end)?.to_vec();
                 output.extend_from_slice(&segment_to_repeat);
                 word_i += 1;
             }
@@ -519,59 +2113,119 @@ fn carmack_decompress(compressed_data: &[u8]) -> Vec<u8> {
         output.extend_from_slice(remainder);
     }
 
-    output
+    Some(output)
 }
 
-fn get_plane(data: &[u8], offset: i32, length: u16, magic_rlew_word: &[u8; 2]) -> [[u16; 64]; 64] {
-    let plane_start = offset as usize;
-    let plane_end = plane_start + length as usize;
-    let decarmackized = carmack_decompress(&data[plane_start..plane_end]);
-    let bytes = rlew_decompress(&decarmackized[4..], magic_rlew_word);
-    let mut bytes = bytes
-        .chunks_exact(2)
-        .map(|word| u16::from_le_bytes(word.try_into().unwrap()));
-    let mut result = [[0; MAP_HEIGHT]; MAP_WIDTH];
-    for y in 0..MAP_HEIGHT {
-        for x in result.iter_mut().take(MAP_WIDTH) {
-            x[y] = bytes.next().unwrap();
+/// The inverse of `rlew_decompress`: runs of more than 3 equal words, and any word that
+/// happens to be the magic word, become (magic word, count, value) triples.
+pub fn rlew_compress(data: &[u8], magic_word: &[u8; 2]) -> Vec<u8> {
+    let words: Vec<&[u8]> = data.chunks_exact(2).collect();
+    let mut output = Vec::with_capacity(data.len());
+    let mut word_i = 0;
+
+    while word_i < words.len() {
+        let word = words[word_i];
+        let count = words[word_i..]
+            .iter()
+            .take(u16::MAX as usize)
+            .take_while(|&&w| w == word)
+            .count();
+        if count > 3 || word == magic_word {
+            output.extend_from_slice(magic_word);
+            output.extend_from_slice(&(count as u16).to_le_bytes());
+            output.extend_from_slice(word);
+            word_i += count;
+        } else {
+            output.extend_from_slice(word);
+            word_i += 1;
         }
     }
-    result
+    output
 }
 
-fn parse_map_data<P: AsRef<Path>>(path: P, meta: MapHead) -> Vec<Map> {
-    let raw_data = fs::read(path).expect("could not read GAMEMAPS file");
-    let mut maps = Vec::new();
-
-    for pointer in meta.pointers {
-        let pointer = pointer as usize;
-        let header = MapLevelHeader::new(&raw_data[pointer..(pointer + 38)]);
+/// The inverse of `carmack_decompress`: the output starts with the length of `data` in bytes,
+/// followed by literal words and pointers to words seen before. Near pointers reach up to
+/// 255 words back, far pointers anywhere from the start of `data`. Pointed words never
+/// overlap the words being produced, so they can be copied at once. Fails if the length
+/// of `data` doesn't fit in the word it's stored in.
+pub fn carmack_compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    const NEAR_POINTER: u8 = 0xA7;
+    const FAR_POINTER: u8 = 0xA8;
+    const MAX_COUNT: usize = 255;
+    let words: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]))
+        .collect();
+    let length = u16::try_from(data.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} bytes are too many to Carmack compress", data.len()),
+        )
+    })?;
+    let mut output = length.to_le_bytes().to_vec();
+    let mut word_i = 0;
 
-        if header.offset_plane0 == 0 {
-            continue;
+    while word_i < words.len() {
+        // longest match starting at each earlier word, the nearest one winning ties
+        let (mut near, mut far) = ((0, 0), (0, 0));
+        for start in (0..word_i).rev() {
+            let max_count = MAX_COUNT.min(word_i - start).min(words.len() - word_i);
+            let count = (0..max_count)
+                .take_while(|&i| words[start + i] == words[word_i + i])
+                .count();
+            if word_i - start <= MAX_COUNT && count > near.0 {
+                near = (count, start);
+            }
+            if count > far.0 {
+                far = (count, start);
+            }
         }
 
-        assert_eq!(64, header.width_n_tiles);
-        assert_eq!(64, header.height_n_tiles);
+        if far.0 >= 3 && far.0 > near.0 && far.1 <= u16::MAX as usize {
+            output.extend_from_slice(&[far.0 as u8, FAR_POINTER]);
+            output.extend_from_slice(&(far.1 as u16).to_le_bytes());
+            word_i += far.0;
+        } else if near.0 >= 2 {
+            output.extend_from_slice(&[near.0 as u8, NEAR_POINTER, (word_i - near.1) as u8]);
+            word_i += near.0;
+        } else {
+            let [low, high] = words[word_i].to_le_bytes();
+            if high == NEAR_POINTER || high == FAR_POINTER {
+                // words that look like pointers are escaped with a count of 0
+                output.extend_from_slice(&[0x00, high, low]);
+            } else {
+                output.extend_from_slice(&[low, high]);
+            }
+            word_i += 1;
+        }
+    }
+    Ok(output)
+}
 
-        // plane2 is unused in wolf, skipping
-        maps.push(Map::new(
-            get_plane(
-                &raw_data,
-                header.offset_plane0,
-                header.length_plane0,
-                &meta.magic,
-            ),
-            get_plane(
-                &raw_data,
-                header.offset_plane1,
-                header.length_plane1,
-                &meta.magic,
-            ),
-            header.name,
-        ));
+/// Reads and decompresses a plane of `size` words, which are stored row by row.
+fn get_plane(
+    file: &DataFile,
+    offset: i32,
+    length: u16,
+    magic_rlew_word: &[u8; 2],
+    size: usize,
+) -> Result<Vec<u16>, CacheError> {
+    let plane_start = offset as usize;
+    let compressed = file.read(plane_start, length as usize)?;
+    let decarmackized = carmack_decompress(&compressed)
+        .filter(|bytes| bytes.len() >= 2)
+        .ok_or_else(|| file.overrun(plane_start))?;
+    let bytes = rlew_decompress(&decarmackized[2..], magic_rlew_word)
+        .ok_or_else(|| file.overrun(plane_start))?;
+    if bytes.len() < size * 2 {
+        return Err(file.truncated(plane_start, length as usize));
     }
-    maps
+
+    Ok(bytes
+        .chunks_exact(2)
+        .take(size)
+        .map(|word| u16::from_le_bytes([word[0], word[1]]))
+        .collect())
 }
 
 #[cfg(test)]
@@ -589,7 +2243,8 @@ mod tests {
             rlew_decompress(
                 &[0x00, 0x01, 0x03, 0x04, 0xFE, 0xFE, 0x05, 0x00, 0xA0, 0x0A],
                 &[0xFE, 0xFE]
-            ),
+            )
+            .unwrap(),
             &[0x00, 0x01, 0x03, 0x04, 0xA0, 0x0A, 0xA0, 0x0A, 0xA0, 0x0A, 0xA0, 0x0A, 0xA0, 0x0A]
         );
     }
@@ -601,8 +2256,12 @@ mod tests {
         // marcolugo@MARCO-LUGO bin % node gamecomp.js -cmp-carmackize < test0.bin > decarmackized0.bin
         // marcolugo@MARCO-LUGO bin % xxd decarmackized0.bin
         // 00000000: 12a7 eeff 34a8 ccdd                      ....4...
+        // with the decompressed length in front, which gamecomp.js leaves out
         assert_eq!(
-            carmack_decompress(&[0x00, 0xA7, 0x12, 0xEE, 0xFF, 0x00, 0xA8, 0x34, 0xCC, 0xDD]),
+            carmack_decompress(&[
+                0x08, 0x00, 0x00, 0xA7, 0x12, 0xEE, 0xFF, 0x00, 0xA8, 0x34, 0xCC, 0xDD
+            ])
+            .unwrap(),
             &[0x12, 0xA7, 0xEE, 0xFF, 0x34, 0xA8, 0xCC, 0xDD]
         );
     }
@@ -614,11 +2273,13 @@ mod tests {
         // marcolugo@MARCO-LUGO bin % xxd decarmackized.bin
         // 00000000: 0001 0203 0405 0607 0809 0a0b 0001 0203  ................
         // 00000010: 0405 0607 0001                           ......
+        // with the decompressed length in front, which gamecomp.js leaves out
         assert_eq!(
             carmack_decompress(&[
-                0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x04, 0xA7,
-                0x06, 0x00, 0x01
-            ]),
+                0x16, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B,
+                0x04, 0xA7, 0x06, 0x00, 0x01
+            ])
+            .unwrap(),
             &[
                 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x00, 0x01,
                 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x00, 0x01
@@ -634,12 +2295,14 @@ mod tests {
         // 00000000: eaeb ec00 a714 defa 34a8 ccdd ab01 eaeb  ........4.......
         // 00000010: ec00 a714 0002 a80a 0002 0203 ff0a 2a00  ..............*.
         // 00000020: a7ff                                     ..
+        // with the decompressed length in front, which gamecomp.js leaves out
         assert_eq!(
             carmack_decompress(&[
-                0xEA, 0xEB, 0xEC, 0x00, 0xA7, 0x14, 0xDE, 0xFA, 0x00, 0xA8, 0x34, 0xCC, 0xDD, 0xAB,
-                0x01, 0x03, 0xA7, 0x07, 0x00, 0x02, 0xA8, 0x0A, 0x00, 0x02, 0x02, 0x03, 0xFF, 0x0A,
-                0x2A, 0x00, 0xA7, 0xFF
-            ]),
+                0x22, 0x00, 0xEA, 0xEB, 0xEC, 0x00, 0xA7, 0x14, 0xDE, 0xFA, 0x00, 0xA8, 0x34, 0xCC,
+                0xDD, 0xAB, 0x01, 0x03, 0xA7, 0x07, 0x00, 0x02, 0xA8, 0x0A, 0x00, 0x02, 0x02, 0x03,
+                0xFF, 0x0A, 0x2A, 0x00, 0xA7, 0xFF
+            ])
+            .unwrap(),
             &[
                 0xEA, 0xEB, 0xEC, 0x00, 0xA7, 0x14, 0xDE, 0xFA, 0x34, 0xA8, 0xCC, 0xDD, 0xAB, 0x01,
                 0xEA, 0xEB, 0xEC, 0x00, 0xA7, 0x14, 0x00, 0x02, 0xA8, 0x0A, 0x00, 0x02, 0x02, 0x03,
@@ -648,20 +2311,454 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rlew_compress() {
+        let data = [
+            0x00, 0x01, 0x03, 0x04, 0xA0, 0x0A, 0xA0, 0x0A, 0xA0, 0x0A, 0xA0, 0x0A, 0xA0, 0x0A,
+            0xFE, 0xFE, 0x05, 0x00, 0x05, 0x00,
+        ];
+        let compressed = rlew_compress(&data, &[0xFE, 0xFE]);
+        assert_eq!(
+            compressed,
+            &[
+                0x00, 0x01, 0x03, 0x04, 0xFE, 0xFE, 0x05, 0x00, 0xA0, 0x0A, 0xFE, 0xFE, 0x01, 0x00,
+                0xFE, 0xFE, 0x05, 0x00, 0x05, 0x00
+            ]
+        );
+        assert_eq!(rlew_decompress(&compressed, &[0xFE, 0xFE]).unwrap(), &data);
+    }
+
+    #[test]
+    fn test_carmack_compress() {
+        // repeats close by and far away, and words that look like pointers
+        let mut data = vec![0x12, 0xA7, 0xEE, 0xFF, 0x34, 0xA8];
+        data.extend((0..600u16).flat_map(|i| (i % 300).to_le_bytes()));
+        data.extend([
+            0xEE, 0xFF, 0x34, 0xA8, 0x00, 0x01, 0x02, 0x00, 0x00, 0x01, 0x02, 0x00,
+        ]);
+        let compressed = carmack_compress(&data).unwrap();
+
+        assert_eq!(&(data.len() as u16).to_le_bytes(), &compressed[..2]);
+        assert_eq!(&[0x00, 0xA7, 0x12], &compressed[2..5]);
+        // the second half is only pointers
+        assert!(compressed.len() < 650, "{}", compressed.len());
+        assert_eq!(carmack_decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_carmack_compress_short() {
+        for data in [
+            &[][..],
+            &[0x01, 0x02],
+            &[0x01, 0x02, 0x01, 0x02, 0x01, 0x02],
+        ] {
+            let compressed = carmack_compress(data).unwrap();
+            assert_eq!(carmack_decompress(&compressed).unwrap(), data);
+        }
+        // lengths whose high byte is that of a pointer
+        let data: Vec<u8> = (0..0xA710u32).map(|i| (i % 6) as u8).collect();
+        let compressed = carmack_compress(&data).unwrap();
+        assert_eq!(&[0x10, 0xA7], &compressed[..2]);
+        assert_eq!(carmack_decompress(&compressed).unwrap(), data);
+        // the length has to fit in a word
+        assert_eq!(
+            io::ErrorKind::InvalidInput,
+            carmack_compress(&[0; 0x10000]).unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_huff_compress() {
+        let data: Vec<u8> = b"a huffman dictionary for this text, aaaaaaaa".to_vec();
+        let huff = huff_dictionary([&data[..], &[0xFF, 0x00]]);
+        assert_eq!(HUFF_NODES, huff.len());
+
+        let compressed = huff_compress(&huff, &data);
+        assert!(compressed.len() < data.len() / 2);
+        assert_eq!(
+            Some(data.clone()),
+            huff_expand(&huff, &compressed, data.len())
+        );
+
+        // bytes that weren't counted still have a code
+        let unseen = [0x80, 0x00, 0x7F];
+        let compressed = huff_compress(&huff, &unseen);
+        assert_eq!(Some(unseen.to_vec()), huff_expand(&huff, &compressed, 3));
+    }
+
     // ignoring this one as it depends on the data files
     #[test]
     #[ignore]
     fn map_file_parsing() {
-        let maps = load_maps();
-        assert_eq!("Wolf1 Map1", maps[0].name);
-        assert_eq!("Wolf1 Map2", maps[1].name);
+        let cache = init(
+            Path::new("data"),
+            GameVariant::Shareware,
+            ChunkLayout::SHAREWARE,
+        )
+        .unwrap();
+        assert_eq!("Wolf1 Map1", cache.get_map(0, 0).unwrap().name);
+        assert_eq!("Wolf1 Map2", cache.get_map(0, 1).unwrap().name);
+    }
+
+    // ignoring this one as it depends on the data files
+    #[test]
+    #[ignore]
+    fn vgagraph_externs_parsing() {
+        let cache = init(
+            Path::new("data"),
+            GameVariant::Shareware,
+            ChunkLayout::SHAREWARE,
+        )
+        .unwrap();
+        assert_eq!(8, cache.get_tile8(NUMTILE8 - 1).unwrap().width);
+        assert_eq!(
+            80 * 25,
+            cache.get_text_screen(ORDERSCREEN).unwrap().cells.len()
+        );
+        assert!(!cache.get_article(T_HELPART).unwrap().pages.is_empty());
+        assert!(!cache.get_demo(T_DEMO0).unwrap().commands.is_empty());
+    }
+
+    #[test]
+    fn test_carmack_decompress_overrun() {
+        // near pointer reaching back further than what was decompressed so far
+        assert_eq!(
+            carmack_decompress(&[0x04, 0x00, 0x00, 0x01, 0x02, 0xA7, 0x04, 0x00]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rlew_decompress_truncated() {
+        assert_eq!(
+            rlew_decompress(&[0x00, 0x01, 0xFE, 0xFE, 0x05, 0x00], &[0xFE, 0xFE]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_huff_expand_source_exhausted() {
+        // every node points straight to a byte value, so each bit is one output byte
+        let huff = vec![(0x41, 0x42); 255];
+        assert_eq!(huff_expand(&huff, &[0b0000_0010], 3), Some(b"ABA".to_vec()));
+        assert_eq!(huff_expand(&huff, &[0b0000_0010], 9), None);
+    }
+
+    #[test]
+    fn test_parse_comp_shape() {
+        let page = [0x1F, 0x00, 0x20, 0x00, 0x08, 0x00, 0x10, 0x00, 0xFF];
+        let shape = parse_comp_shape(&page).unwrap();
+        assert_eq!((31, 32), (shape.left_pix, shape.right_pix));
+        assert_eq!(vec![8, 16], shape.dataofs);
+
+        // right_pix past the 64 columns of a sprite
+        assert!(parse_comp_shape(&[0x00, 0x00, 0x40, 0x00]).is_none());
+        // fewer column offsets than columns
+        assert!(parse_comp_shape(&page[..6]).is_none());
+    }
+
+    #[test]
+    fn test_parse_sprite() {
+        // columns 2 and 3: the first with a post covering rows 3 and 4, whose pixels are
+        // at byte 27 + row, the second with posts for rows 0 and 62 to 63
+        let mut page = vec![0x02, 0x00, 0x03, 0x00, 0x08, 0x00, 0x10, 0x00];
+        page.extend([10, 0, 27, 0, 6, 0, 0, 0]);
+        page.extend([2, 0, 32, 0, 0, 0, 128, 0, 227, 255, 124, 0, 0, 0]);
+        page.extend([0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+        let sprite = parse_sprite(&page).unwrap();
+
+        assert_eq!((2, 3), (sprite.left_pix, sprite.right_pix));
+        assert_eq!(
+            vec![
+                vec![Span {
+                    start: 3,
+                    pixels: vec![0xBB, 0xCC]
+                }],
+                vec![
+                    Span {
+                        start: 0,
+                        pixels: vec![0xDD]
+                    },
+                    Span {
+                        start: 62,
+                        pixels: vec![0xEE, 0xFF]
+                    }
+                ],
+            ],
+            sprite.columns
+        );
+        assert_eq!(Some(0xCC), sprite.pixel(2, 4));
+        assert_eq!(Some(0xFF), sprite.pixel(3, 63));
+        assert_eq!(None, sprite.pixel(2, 5));
+        assert_eq!(None, sprite.pixel(1, 3));
+
+        // pixels past the end of the page
+        assert!(parse_sprite(&page[..page.len() - 1]).is_none());
+        // missing end of the post list
+        assert!(parse_sprite(&page[..14]).is_none());
+    }
+
+    #[test]
+    fn test_encode_sprite() {
+        let sprite = Sprite {
+            left_pix: 10,
+            right_pix: 12,
+            columns: vec![
+                vec![Span {
+                    start: 0,
+                    pixels: vec![1, 2, 3],
+                }],
+                vec![],
+                vec![
+                    Span {
+                        start: 5,
+                        pixels: vec![4],
+                    },
+                    Span {
+                        start: 60,
+                        pixels: vec![5, 6, 7, 8],
+                    },
+                ],
+            ],
+        };
+        let page = encode_sprite(&sprite);
+        // header, pixels, and a post per span plus one to end each column
+        assert_eq!(4 + 3 * 2 + 8 + 3 * 6 + 3 * 2, page.len());
+        assert_eq!(Some(sprite), parse_sprite(&page));
+    }
+
+    #[test]
+    fn test_parse_font() {
+        // 'A' is 2 pixels wide and 'B' is 3, everything else is empty
+        let mut data = vec![0; 2 + 256 * 3];
+        data[0] = 2;
+        let glyphs_start = data.len() as u16;
+        data[2 + 65 * 2..2 + 65 * 2 + 2].copy_from_slice(&glyphs_start.to_le_bytes());
+        data[2 + 66 * 2..2 + 66 * 2 + 2].copy_from_slice(&(glyphs_start + 4).to_le_bytes());
+        data[2 + 512 + 65] = 2;
+        data[2 + 512 + 66] = 3;
+        data.extend([1, 0, 0, 1]);
+        data.extend([1, 1, 1, 0, 1, 0]);
+
+        let font = parse_font(data).unwrap();
+        assert_eq!(2, font.height);
+        assert_eq!((2, &[1, 0, 0, 1][..]), font.glyph('A'));
+        assert_eq!((3, &[1, 1, 1, 0, 1, 0][..]), font.glyph('B'));
+        assert_eq!((0, &[][..]), font.glyph('\u{263A}'));
+        assert_eq!(7, font.measure("ABA"));
+        assert!(parse_font(vec![0; 100]).is_none());
+    }
+
+    #[test]
+    fn test_parse_text_screen() {
+        let mut data = vec![0xFD, 0x00, 0xB8, 0x00, 0x00, 0xA0, 0x0F];
+        data.extend((0..TEXT_COLUMNS * 2).flat_map(|i| [b'a' + (i % 26) as u8, 0x1E]));
+        let screen = parse_text_screen(data).unwrap();
+        assert_eq!(160, screen.cells.len());
+        assert_eq!((b'b', 0x1E), screen.cells[1]);
+        assert_eq!(2, screen.rows().count());
+        assert_eq!((b'c', 0x1E), screen.rows().nth(1).unwrap()[0]);
+    }
+
+    #[test]
+    fn test_parse_article() {
+        let article = parse_article(b"^P\r\n^C0First page\r\n^P\r\nSecond^E\r\nignored");
+        assert_eq!(vec!["^C0First page\n", "Second"], article.pages);
+    }
+
+    #[test]
+    fn test_parse_demo() {
+        // 2 tics, the trailing bytes are past the demo length
+        let data = vec![3, 10, 0, 0, 0x01, 0xFF, 0x00, 0x00, 0x05, 0x7F, 0xEE, 0xEE];
+        let demo = parse_demo(data).unwrap();
+        assert_eq!(3, demo.map);
+        assert_eq!(
+            vec![
+                DemoCommand {
+                    buttons: 1,
+                    dx: -1,
+                    dy: 0
+                },
+                DemoCommand {
+                    buttons: 0,
+                    dx: 5,
+                    dy: 127
+                },
+            ],
+            demo.commands
+        );
+        assert!(parse_demo(vec![1]).is_none());
+    }
+
+    #[test]
+    fn test_parse_palette() {
+        let data: Vec<u8> = (0..PALETTE_SIZE).map(|i| (i % 64) as u8).collect();
+        let palette = parse_palette(data).unwrap();
+        assert_eq!(256, palette.colors.len());
+        assert_eq!((3, 4, 5), palette.colors[1]);
+        assert!(parse_palette(vec![0; 10]).is_none());
+    }
+
+    #[test]
+    fn test_parse_pc_sound() {
+        let chunk = [0x03, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x20, 0x21, 0x00, 0xFF];
+        let sound = parse_pc_sound(&chunk).unwrap();
+        assert_eq!(10, sound.priority);
+        assert_eq!(vec![0x20, 0x21, 0x00], sound.data);
+
+        assert!(parse_pc_sound(&chunk[..8]).is_none());
+    }
+
+    #[test]
+    fn test_parse_adlib_sound() {
+        let mut chunk = vec![0x02, 0x00, 0x00, 0x00, 0x05, 0x00];
+        chunk.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 0, 0, 0]);
+        chunk.extend_from_slice(&[0x04, 0x80, 0x90, 0x00]);
+        let sound = parse_adlib_sound(&chunk).unwrap();
+        assert_eq!(5, sound.priority);
+        assert_eq!(1, sound.instrument.m_char);
+        assert_eq!(10, sound.instrument.c_wave);
+        assert_eq!(11, sound.instrument.n_conn);
+        assert_eq!(4, sound.block);
+        assert_eq!(vec![0x80, 0x90], sound.data);
+    }
+
+    #[test]
+    fn test_parse_imf_song() {
+        let chunk = [
+            0x08, 0x00, 0xB0, 0x20, 0x05, 0x00, 0xA0, 0x41, 0x00, 0x01, 0xEE,
+        ];
+        let song = parse_imf_song(&chunk).unwrap();
+        assert_eq!(
+            vec![
+                ImfCommand {
+                    register: 0xB0,
+                    value: 0x20,
+                    delay: 5
+                },
+                ImfCommand {
+                    register: 0xA0,
+                    value: 0x41,
+                    delay: 256
+                }
+            ],
+            song.commands
+        );
+        assert!(parse_imf_song(&chunk[..6]).is_none());
+    }
+
+    #[test]
+    fn test_parse_digi_list() {
+        // two texture pages, sounds start at page 2 and the info page is the last one
+        let pages = [
+            (100, 4096),
+            (200, 4096),
+            (300, 4096),
+            (400, 4096),
+            (500, 1000),
+            (600, 0xFFFF),
+            (700, 0x11),
+            (800, 12),
+        ];
+        // first sound spans 3 pages, the second one 2 pages whose padding goes over 64KiB
+        let info = [0x00, 0x00, 0x00, 0x23, 0x03, 0x00, 0xF0, 0xFF];
+        assert_eq!(
+            vec![
+                DigiEntry {
+                    start_page: 2,
+                    end_page: 5,
+                    length: 0x2300
+                },
+                DigiEntry {
+                    start_page: 5,
+                    end_page: 7,
+                    length: 0xFFF0
+                }
+            ],
+            parse_digi_list(&info, &pages, 2)
+        );
+
+        // entries pointing past the sound pages end the list
+        let info = [0x00, 0x00, 0x00, 0x23, 0x09, 0x00, 0xF0, 0xFF];
+        assert_eq!(1, parse_digi_list(&info, &pages, 2).len());
+
+        // and so do entries starting before the one ahead of them
+        let info = [
+            0x03, 0x00, 0x00, 0x23, 0x01, 0x00, 0xF0, 0xFF, 0x02, 0x00, 0x11, 0x00,
+        ];
+        assert_eq!(
+            vec![DigiEntry {
+                start_page: 5,
+                end_page: 7,
+                length: 0x2300
+            }],
+            parse_digi_list(&info, &pages, 2)
+        );
+    }
+
+    #[test]
+    fn test_memoise_decodes_once() {
+        let cell = OnceCell::new();
+        assert_eq!(Ok(&1), memoise(&cell, || Ok(1)).map_err(|_| ()));
+        assert_eq!(Ok(&1), memoise(&cell, || Ok(2)).map_err(|_| ()));
+    }
+
+    #[test]
+    fn data_file_falls_back_to_lowercase_names() {
+        let dir = std::env::temp_dir().join("rustenstein_data_file_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("vswap.wl6"), []).unwrap();
+
+        assert_eq!(
+            dir.join("vswap.wl6"),
+            data_file(&dir, "VSWAP", GameVariant::Registered)
+        );
+        assert_eq!(
+            dir.join("VSWAP.WL1"),
+            data_file(&dir, "VSWAP", GameVariant::Shareware)
+        );
+        assert_eq!(None, detect_variant(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pic_names_follow_the_constants() {
+        assert_eq!(Some("H_BJPIC"), pic_name(H_BJPIC));
+        assert_eq!(Some("TITLEPIC"), pic_name(TITLEPIC));
+        assert_eq!(Some("GETPSYCHEDPIC"), pic_name(GETPSYCHEDPIC));
+        assert_eq!(None, pic_name(GETPSYCHEDPIC + 1));
+        assert_eq!(None, pic_name(0));
+    }
+
+    #[test]
+    fn shareware_layout_follows_the_constants() {
+        let layout = ChunkLayout::SHAREWARE;
+        assert_eq!(STARTTILE8, layout.start_tile8());
+        assert_eq!(STARTEXTERNS, layout.start_externs());
+        assert_eq!(T_ENDART6 + 1, layout.start_externs() + layout.externs.len());
+        assert_eq!(
+            Some((ExternKind::TextScreen, 1)),
+            layout.extern_index(ERRORSCREEN)
+        );
+        assert_eq!(Some((ExternKind::Demo, 0)), layout.extern_index(T_DEMO0));
+        assert_eq!(
+            Some((ExternKind::Article, 6)),
+            layout.extern_index(T_ENDART6)
+        );
+        assert_eq!(None, layout.extern_index(T_ENDART6 + 1));
+        assert_eq!(None, layout.extern_index(STARTTILE8));
     }
 
     #[test]
     #[ignore]
     fn dump_map0_plane0_printout() {
-        let maps = load_maps();
+        let cache = init(
+            Path::new("data"),
+            GameVariant::Shareware,
+            ChunkLayout::SHAREWARE,
+        )
+        .unwrap();
         let mut file = fs::File::create("test_map0.txt").unwrap();
-        write!(file, "{}", maps[0]).unwrap();
+        write!(file, "{}", cache.get_map(0, 0).unwrap()).unwrap();
     }
 }
@@ -1,5 +1,6 @@
 use std::f64::consts::PI;
 
+// the size of the original maps, which the scale of a tile in map units comes from
 pub const MAP_WIDTH: usize = 64;
 pub const MAP_HEIGHT: usize = 64;
 pub const WIDTH_2D: u32 = 1024;
@@ -14,6 +15,9 @@ pub const STATUS_LINES: u32 = 40;
 pub const BASE_WIDTH: u32 = 320;
 pub const BASE_HEIGHT: u32 = 200;
 pub const WALLPIC_WIDTH: usize = 64;
+pub const WALLPIC_HEIGHT: usize = 64;
+// sprites are drawn in a square of this many pixels
+pub const SPRITE_SIZE: usize = 64;
 
 // ok this is not a constant, we may move it to an util module later, or rename this
 pub fn norm_angle(a: f64) -> f64 {
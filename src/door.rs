@@ -0,0 +1,269 @@
+// The doors of a level and how far open they are. Like in the original, a door slides
+// open in 64 tics, stays open for 300 tics and then slides closed again, unless the
+// player is standing in its way. A tic is 1/70 of a second.
+
+use crate::map::{Map, Tile};
+use crate::player::Keys;
+
+pub const TICS_PER_SECOND: f64 = 70.0;
+/// How much a door opens or closes per tic, it's fully open at 1.
+const DOOR_SPEED: f64 = 1.0 / 64.0;
+/// How long a door stays open before closing by itself.
+const OPEN_TICS: f64 = 300.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DoorState {
+    Closed,
+    Opening,
+    Open,
+    Closing,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Door {
+    pub x: usize,
+    pub y: usize,
+    pub vertical: bool,
+    /// 0 for normal doors, 1 to 4 for the locked ones and 5 for the elevator door.
+    pub lock: u16,
+    pub state: DoorState,
+    /// How far the door slid open, from 0 (closed) to 1 (open).
+    pub position: f64,
+    /// Tics spent fully open.
+    open_tics: f64,
+}
+
+impl Door {
+    /// The wall page the door is drawn with. The door pages are the last 8 wall pages:
+    /// normal, elevator and locked doors, each horizontal then vertical, around the two
+    /// door frame ones.
+    pub fn texture(&self, num_textures: usize) -> usize {
+        let door_wall = num_textures - 8;
+        let page = match self.lock {
+            0 => 0,
+            5 => 4,
+            _ => 6,
+        };
+        door_wall + page + self.vertical as usize
+    }
+}
+
+/// Every door of a level, found by position.
+#[derive(Debug, Clone)]
+pub struct Doors {
+    doors: Vec<Door>,
+    width: usize,
+    /// Index of the door at each position of the map, row by row.
+    index: Vec<Option<usize>>,
+}
+
+impl Doors {
+    /// The doors of a map, all closed.
+    pub fn new(map: &Map) -> Self {
+        let mut doors = Vec::new();
+        let mut index = vec![None; map.width() * map.height()];
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                if let Tile::Door { vertical, lock } = map.tile_at(x, y) {
+                    index[y * map.width() + x] = Some(doors.len());
+                    doors.push(Door {
+                        x,
+                        y,
+                        vertical,
+                        lock,
+                        state: DoorState::Closed,
+                        position: 0.0,
+                        open_tics: 0.0,
+                    });
+                }
+            }
+        }
+        Doors {
+            doors,
+            width: map.width(),
+            index,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Door> {
+        self.doors.iter()
+    }
+
+    pub fn door_at(&self, x: usize, y: usize) -> Option<&Door> {
+        let i = self.position_index(x, y)?;
+        Some(&self.doors[i])
+    }
+
+    fn door_at_mut(&mut self, x: usize, y: usize) -> Option<&mut Door> {
+        let i = self.position_index(x, y)?;
+        Some(&mut self.doors[i])
+    }
+
+    fn position_index(&self, x: usize, y: usize) -> Option<usize> {
+        if x >= self.width {
+            return None;
+        }
+        *self.index.get(y * self.width + x)?
+    }
+
+    /// How far the door at the given position is open, 0 where there's no door.
+    pub fn open_amount(&self, x: usize, y: usize) -> f64 {
+        self.door_at(x, y).map_or(0.0, |door| door.position)
+    }
+
+    /// Whether there's a door at the given position that isn't fully open.
+    pub fn blocks(&self, x: usize, y: usize) -> bool {
+        self.door_at(x, y)
+            .is_some_and(|door| door.state != DoorState::Open)
+    }
+
+    /// Opens the door at the given position, or closes it if it's open or opening.
+    /// Returns false if there's no door there, or if it's locked and `keys` don't open it.
+    pub fn operate(&mut self, x: usize, y: usize, keys: Keys) -> bool {
+        let door = match self.door_at_mut(x, y) {
+            Some(door) if keys.open(door.lock) => door,
+            _ => return false,
+        };
+        door.state = match door.state {
+            DoorState::Closed | DoorState::Closing => DoorState::Opening,
+            DoorState::Open | DoorState::Opening => DoorState::Closing,
+        };
+        door.open_tics = 0.0;
+        true
+    }
+
+    /// Moves the doors along for the time elapsed. Doors don't close on the tile the
+    /// player is standing on.
+    pub fn update(&mut self, tics: f64, player_tile: (usize, usize)) {
+        for door in &mut self.doors {
+            let in_the_way = (door.x, door.y) == player_tile;
+            match door.state {
+                DoorState::Opening => {
+                    door.position += DOOR_SPEED * tics;
+                    if door.position >= 1.0 {
+                        door.position = 1.0;
+                        door.state = DoorState::Open;
+                        door.open_tics = 0.0;
+                    }
+                }
+                DoorState::Open => {
+                    door.open_tics += tics;
+                    if door.open_tics >= OPEN_TICS && !in_the_way {
+                        door.state = DoorState::Closing;
+                    }
+                }
+                DoorState::Closing if in_the_way => door.state = DoorState::Opening,
+                DoorState::Closing => {
+                    door.position -= DOOR_SPEED * tics;
+                    if door.position <= 0.0 {
+                        door.position = 0.0;
+                        door.state = DoorState::Closed;
+                    }
+                }
+                DoorState::Closed => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A corridor with a normal vertical door and a locked horizontal one.
+    fn test_map() -> Map {
+        Map::new(
+            4,
+            1,
+            vec![108, 90, 108, 93],
+            vec![0; 4],
+            "Doors".to_string(),
+        )
+    }
+
+    #[test]
+    fn doors_are_found_closed() {
+        let doors = Doors::new(&test_map());
+        assert_eq!(2, doors.iter().count());
+        let door = doors.door_at(3, 0).unwrap();
+        assert_eq!(
+            (false, 1, DoorState::Closed),
+            (door.vertical, door.lock, door.state)
+        );
+        assert!(doors.door_at(0, 0).is_none());
+        assert!(doors.door_at(7, 0).is_none());
+        assert!(doors.blocks(1, 0));
+        assert!(!doors.blocks(0, 0));
+    }
+
+    #[test]
+    fn doors_open_wait_and_close() {
+        let mut doors = Doors::new(&test_map());
+        let away = (0, 0);
+        assert!(doors.operate(1, 0, Keys::default()));
+        assert!(!doors.operate(0, 0, Keys::default()));
+
+        doors.update(32.0, away);
+        assert_eq!(0.5, doors.open_amount(1, 0));
+        assert!(doors.blocks(1, 0));
+        doors.update(32.0, away);
+        assert_eq!(DoorState::Open, doors.door_at(1, 0).unwrap().state);
+        assert!(!doors.blocks(1, 0));
+
+        doors.update(299.0, away);
+        assert_eq!(DoorState::Open, doors.door_at(1, 0).unwrap().state);
+        doors.update(1.0, away);
+        assert_eq!(DoorState::Closing, doors.door_at(1, 0).unwrap().state);
+        assert!(doors.blocks(1, 0));
+        doors.update(64.0, away);
+        assert_eq!(DoorState::Closed, doors.door_at(1, 0).unwrap().state);
+        assert_eq!(0.0, doors.open_amount(1, 0));
+    }
+
+    #[test]
+    fn doors_wait_for_the_player() {
+        let mut doors = Doors::new(&test_map());
+        doors.operate(1, 0, Keys::default());
+        doors.update(64.0, (1, 0));
+        doors.update(400.0, (1, 0));
+        assert_eq!(DoorState::Open, doors.door_at(1, 0).unwrap().state);
+
+        // closing on the player opens it again
+        doors.update(1.0, (0, 0));
+        doors.update(8.0, (0, 0));
+        doors.update(1.0, (1, 0));
+        assert_eq!(DoorState::Opening, doors.door_at(1, 0).unwrap().state);
+    }
+
+    #[test]
+    fn using_a_door_toggles_it() {
+        let mut doors = Doors::new(&test_map());
+        doors.operate(1, 0, Keys::default());
+        doors.update(16.0, (0, 0));
+        doors.operate(1, 0, Keys::default());
+        assert_eq!(DoorState::Closing, doors.door_at(1, 0).unwrap().state);
+        doors.update(16.0, (0, 0));
+        assert_eq!(DoorState::Closed, doors.door_at(1, 0).unwrap().state);
+    }
+
+    #[test]
+    fn locked_doors_need_their_key() {
+        let mut doors = Doors::new(&test_map());
+        let mut keys = Keys::default();
+        assert!(!doors.operate(3, 0, keys));
+        keys.silver = true;
+        assert!(!doors.operate(3, 0, keys));
+        assert_eq!(DoorState::Closed, doors.door_at(3, 0).unwrap().state);
+        keys.gold = true;
+        assert!(doors.operate(3, 0, keys));
+        assert_eq!(DoorState::Opening, doors.door_at(3, 0).unwrap().state);
+    }
+
+    #[test]
+    fn door_textures() {
+        let doors = Doors::new(&test_map());
+        // the shareware VSWAP has 106 wall pages, the door ones from 98 on
+        assert_eq!(99, doors.door_at(1, 0).unwrap().texture(106));
+        assert_eq!(104, doors.door_at(3, 0).unwrap().texture(106));
+    }
+}
@@ -0,0 +1,644 @@
+// Writes maps to formats other tools understand: Tiled's TMX and JSON maps, and a plain
+// JSON document of our own, described below.
+//
+// The plain JSON format is an object with these fields:
+//
+// * `name`: the level name, e.g. "Wolf1 Map1".
+// * `width`, `height`: size of the map in tiles.
+// * `plane0`, `plane1`: the raw words of the walls and objects planes, as `height` rows
+//   of `width` numbers. Row `y`, column `x` is the tile at x, y.
+// * `plane2`: the third plane, laid out the same way, only there if the map has one.
+// * `tiles`: plane 0 decoded, laid out like the planes. Each tile is
+//   `{"floor": {"area": <number>}}`, `"ambush_floor"`, `{"wall": <texture>}`, `"elevator"`,
+//   `"fake_elevator"` or `{"door": {"vertical": <bool>, "lock": <number>}}`.
+// * `actors`: plane 1 decoded, as a list of `{"x", "y", "actor"}` objects for the tiles
+//   that have one. `actor` is `{"player": <direction>}`, `{"enemy": <enemy>}`,
+//   `{"static": <object>}`, `"dead_guard"` or `"push_wall"`, with the direction being one
+//   of `"north"`, `"east"`, `"south"` or `"west"`, and the object the snake case name of
+//   a `map::Static`, e.g. `"green_barrel"`. An enemy is `{"kind", "direction",
+//   "patrolling", "min_skill"}`, the kind being the snake case name of a `map::Enemy`,
+//   e.g. `"officer"`, and bosses having no direction.
+// * `player_start`: `{"x", "y", "direction"}`, or `null` for maps without a player.
+//
+// In Tiled maps, the planes are the `plane0`, `plane1` and `plane2` tile layers, where the global
+// tile id of each tile is the raw word (0 being no tile). The decoded walls are in the
+// `walls` tile layer, doors and actors are objects in the `doors` and `actors` layers,
+// their payload as properties, and the name and player start are map properties.
+
+use crate::cache::{Cache, CacheError};
+use crate::extract::ExtractError;
+use crate::map::{Actor, Direction, Map, Tile, ELEVATOR_TILE, FAKE_ELEVATOR_TILE};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Size in pixels of a tile in Tiled maps, the size of a wall texture.
+const TILE_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Tmx,
+    TiledJson,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Tmx => "tmx",
+            Format::TiledJson => "tmj",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "tmx" => Ok(Format::Tmx),
+            "tmj" => Ok(Format::TiledJson),
+            _ => Err(format!("unknown map format {}", s)),
+        }
+    }
+}
+
+/// Writes every level of the data set to `out_dir`, named after its episode and level
+/// number, e.g. `E1M1.json`. Returns how many were written.
+pub fn export_all(cache: &Cache, out_dir: &Path, format: Format) -> Result<usize, ExtractError> {
+    let write_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| ExtractError::Write { path, source }
+    };
+    fs::create_dir_all(out_dir).map_err(write_error(out_dir))?;
+
+    let mut count = 0;
+    for index in 0..cache.num_maps() {
+        let (episode, level) = (index / 10, index % 10);
+        let map = match cache.get_map(episode, level) {
+            Ok(map) => map,
+            Err(CacheError::MissingChunk { .. }) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let path = out_dir.join(format!(
+            "E{}M{}.{}",
+            episode + 1,
+            level + 1,
+            format.extension()
+        ));
+        fs::write(&path, export(&map, format)).map_err(write_error(&path))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MapDocument {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub plane0: Vec<Vec<u16>>,
+    pub plane1: Vec<Vec<u16>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plane2: Option<Vec<Vec<u16>>>,
+    pub tiles: Vec<Vec<Tile>>,
+    pub actors: Vec<PlacedActor>,
+    pub player_start: Option<PlayerStart>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlacedActor {
+    pub x: usize,
+    pub y: usize,
+    pub actor: Actor,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlayerStart {
+    pub x: usize,
+    pub y: usize,
+    pub direction: Direction,
+}
+
+impl MapDocument {
+    pub fn new(map: &Map) -> Self {
+        let rows = |cell: &dyn Fn(usize, usize) -> u16| -> Vec<Vec<u16>> {
+            positions_by_row(map)
+                .map(|row| row.map(|(x, y)| cell(x, y)).collect())
+                .collect()
+        };
+        let mut actors = Vec::new();
+        for row in positions_by_row(map) {
+            for (x, y) in row {
+                if let Some(actor) = map.actor_at(x, y) {
+                    actors.push(PlacedActor { x, y, actor });
+                }
+            }
+        }
+
+        MapDocument {
+            name: map.name.clone(),
+            width: map.width(),
+            height: map.height(),
+            plane0: rows(&|x, y| map.tile_code(x, y)),
+            plane1: rows(&|x, y| map.actor_code(x, y)),
+            plane2: map
+                .plane(2)
+                .map(|words| words.chunks(map.width()).map(<[u16]>::to_vec).collect()),
+            tiles: positions_by_row(map)
+                .map(|row| row.map(|(x, y)| map.tile_at(x, y)).collect())
+                .collect(),
+            actors,
+            player_start: map.player_start().map(|(x, y, direction)| PlayerStart {
+                x,
+                y,
+                direction,
+            }),
+        }
+    }
+
+    /// The map the planes describe, `None` if they don't match the width and height. The
+    /// decoded fields are only there for other tools and are ignored.
+    pub fn to_map(&self) -> Option<Map> {
+        let words = |rows: &Vec<Vec<u16>>| -> Option<Vec<u16>> {
+            if rows.len() != self.height || rows.iter().any(|row| row.len() != self.width) {
+                return None;
+            }
+            Some(rows.concat())
+        };
+        let map = Map::new(
+            self.width,
+            self.height,
+            words(&self.plane0)?,
+            words(&self.plane1)?,
+            self.name.clone(),
+        );
+        match &self.plane2 {
+            Some(plane2) => Some(map.with_plane2(words(plane2)?)),
+            None => Some(map),
+        }
+    }
+}
+
+/// Tile positions, row by row.
+fn positions_by_row(map: &Map) -> impl Iterator<Item = impl Iterator<Item = (usize, usize)>> {
+    let width = map.width();
+    (0..map.height()).map(move |y| (0..width).map(move |x| (x, y)))
+}
+
+pub fn export(map: &Map, format: Format) -> String {
+    match format {
+        Format::Json => to_json(map),
+        Format::Tmx => to_tmx(map),
+        Format::TiledJson => to_tiled_json(map),
+    }
+}
+
+pub fn to_json(map: &Map) -> String {
+    serde_json::to_string_pretty(&MapDocument::new(map)).expect("maps serialize to JSON")
+}
+
+/// An object of a Tiled object layer, with its payload as properties.
+struct TiledObject {
+    kind: String,
+    x: usize,
+    y: usize,
+    properties: Vec<(String, Value)>,
+}
+
+impl TiledObject {
+    /// Turns the serialized form of a tile or actor into an object: its variant name
+    /// is the object type, and its fields the properties.
+    fn new<T: Serialize>(x: usize, y: usize, value: &T) -> Self {
+        let (kind, properties) = match serde_json::to_value(value).expect("serializable") {
+            Value::Object(variant) => {
+                let (kind, payload) = variant.into_iter().next().expect("one variant");
+                let properties = match payload {
+                    Value::Object(fields) => fields.into_iter().collect(),
+                    value => vec![("value".to_string(), value)],
+                };
+                (kind, properties)
+            }
+            Value::String(kind) => (kind, Vec::new()),
+            other => (other.to_string(), Vec::new()),
+        };
+        TiledObject {
+            kind,
+            x,
+            y,
+            properties,
+        }
+    }
+}
+
+fn doors(map: &Map) -> Vec<TiledObject> {
+    positions_by_row(map)
+        .flatten()
+        .filter_map(|(x, y)| match map.tile_at(x, y) {
+            door @ Tile::Door { .. } => Some(TiledObject::new(x, y, &door)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn actors(map: &Map) -> Vec<TiledObject> {
+    positions_by_row(map)
+        .flatten()
+        .filter_map(|(x, y)| {
+            map.actor_at(x, y)
+                .map(|actor| TiledObject::new(x, y, &actor))
+        })
+        .collect()
+}
+
+/// The tile layers of a Tiled map, with the gid of each tile.
+fn tile_layers(map: &Map) -> Vec<(&'static str, Vec<u16>)> {
+    let layer = |cell: &dyn Fn(usize, usize) -> u16| {
+        positions_by_row(map)
+            .flatten()
+            .map(|(x, y)| cell(x, y))
+            .collect()
+    };
+    let mut layers = vec![
+        ("plane0", layer(&|x, y| map.tile_code(x, y))),
+        ("plane1", layer(&|x, y| map.actor_code(x, y))),
+    ];
+    if let Some(words) = map.plane(2) {
+        layers.push(("plane2", words.to_vec()));
+    }
+    layers.push((
+        "walls",
+        layer(&|x, y| match map.tile_at(x, y) {
+            Tile::Wall(texture) => texture,
+            Tile::Elevator => ELEVATOR_TILE,
+            Tile::FakeElevator => FAKE_ELEVATOR_TILE,
+            _ => 0,
+        }),
+    ));
+    layers
+}
+
+fn map_properties(map: &Map) -> Vec<(String, Value)> {
+    let mut properties = vec![("name".to_string(), json!(map.name))];
+    if let Some((x, y, direction)) = map.player_start() {
+        properties.push(("player_x".to_string(), json!(x)));
+        properties.push(("player_y".to_string(), json!(y)));
+        properties.push(("player_direction".to_string(), json!(direction)));
+    }
+    properties
+}
+
+/// Tiled property type and value as a string, for a JSON value.
+fn property_type(value: &Value) -> (&'static str, String) {
+    match value {
+        Value::Bool(b) => ("bool", b.to_string()),
+        Value::Number(n) if n.is_f64() => ("float", n.to_string()),
+        Value::Number(n) => ("int", n.to_string()),
+        Value::String(s) => ("string", s.clone()),
+        other => ("string", other.to_string()),
+    }
+}
+
+pub fn to_tmx(map: &Map) -> String {
+    let (width, height) = (map.width(), map.height());
+    let object_layers = [("doors", doors(map)), ("actors", actors(map))];
+    let num_objects: usize = object_layers.iter().map(|(_, objects)| objects.len()).sum();
+    let tile_layers = tile_layers(map);
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        out,
+        "<map version=\"1.10\" orientation=\"orthogonal\" renderorder=\"right-down\" \
+         width=\"{}\" height=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" infinite=\"0\" \
+         nextlayerid=\"{}\" nextobjectid=\"{}\">",
+        width,
+        height,
+        TILE_SIZE,
+        TILE_SIZE,
+        tile_layers.len() + object_layers.len() + 1,
+        num_objects + 1
+    )
+    .unwrap();
+    write_tmx_properties(&mut out, " ", &map_properties(map));
+    writeln!(
+        out,
+        " <tileset firstgid=\"1\" name=\"codes\" tilewidth=\"{0}\" tileheight=\"{0}\" \
+         tilecount=\"65535\" columns=\"0\"/>",
+        TILE_SIZE
+    )
+    .unwrap();
+
+    let mut layer_id = 1;
+    for (name, gids) in &tile_layers {
+        writeln!(
+            out,
+            " <layer id=\"{}\" name=\"{}\" width=\"{}\" height=\"{}\">",
+            layer_id, name, width, height
+        )
+        .unwrap();
+        out.push_str("  <data encoding=\"csv\">\n");
+        let rows: Vec<String> = gids
+            .chunks(width)
+            .map(|row| {
+                row.iter()
+                    .map(|gid| gid.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect();
+        out.push_str(&rows.join(",\n"));
+        out.push_str("\n  </data>\n </layer>\n");
+        layer_id += 1;
+    }
+
+    let mut object_id = 1;
+    for (name, objects) in &object_layers {
+        writeln!(out, " <objectgroup id=\"{}\" name=\"{}\">", layer_id, name).unwrap();
+        for object in objects {
+            writeln!(
+                out,
+                "  <object id=\"{}\" name=\"{2}\" type=\"{2}\" x=\"{3}\" y=\"{4}\" \
+                 width=\"{1}\" height=\"{1}\">",
+                object_id,
+                TILE_SIZE,
+                xml_escape(&object.kind),
+                object.x * TILE_SIZE,
+                object.y * TILE_SIZE
+            )
+            .unwrap();
+            write_tmx_properties(&mut out, "   ", &object.properties);
+            out.push_str("  </object>\n");
+            object_id += 1;
+        }
+        out.push_str(" </objectgroup>\n");
+        layer_id += 1;
+    }
+    out.push_str("</map>\n");
+    out
+}
+
+fn write_tmx_properties(out: &mut String, indent: &str, properties: &[(String, Value)]) {
+    if properties.is_empty() {
+        return;
+    }
+    writeln!(out, "{}<properties>", indent).unwrap();
+    for (name, value) in properties {
+        let (kind, value) = property_type(value);
+        writeln!(
+            out,
+            "{} <property name=\"{}\" type=\"{}\" value=\"{}\"/>",
+            indent,
+            xml_escape(name),
+            kind,
+            xml_escape(&value)
+        )
+        .unwrap();
+    }
+    writeln!(out, "{}</properties>", indent).unwrap();
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn tiled_json_properties(properties: &[(String, Value)]) -> Value {
+    properties
+        .iter()
+        .map(|(name, value)| {
+            let (kind, _) = property_type(value);
+            json!({ "name": name, "type": kind, "value": value })
+        })
+        .collect()
+}
+
+pub fn to_tiled_json(map: &Map) -> String {
+    let (width, height) = (map.width(), map.height());
+    let mut layers = Vec::new();
+    let mut layer_id = 1;
+    for (name, gids) in tile_layers(map) {
+        layers.push(json!({
+            "id": layer_id,
+            "name": name,
+            "type": "tilelayer",
+            "x": 0,
+            "y": 0,
+            "width": width,
+            "height": height,
+            "opacity": 1,
+            "visible": true,
+            "data": gids,
+        }));
+        layer_id += 1;
+    }
+
+    let mut object_id = 1;
+    for (name, objects) in [("doors", doors(map)), ("actors", actors(map))] {
+        let objects: Vec<Value> = objects
+            .into_iter()
+            .map(|object| {
+                let value = json!({
+                    "id": object_id,
+                    "name": object.kind,
+                    "type": object.kind,
+                    "x": object.x * TILE_SIZE,
+                    "y": object.y * TILE_SIZE,
+                    "width": TILE_SIZE,
+                    "height": TILE_SIZE,
+                    "rotation": 0,
+                    "visible": true,
+                    "properties": tiled_json_properties(&object.properties),
+                });
+                object_id += 1;
+                value
+            })
+            .collect();
+        layers.push(json!({
+            "id": layer_id,
+            "name": name,
+            "type": "objectgroup",
+            "x": 0,
+            "y": 0,
+            "opacity": 1,
+            "visible": true,
+            "draworder": "topdown",
+            "objects": objects,
+        }));
+        layer_id += 1;
+    }
+
+    let document = json!({
+        "type": "map",
+        "version": "1.10",
+        "orientation": "orthogonal",
+        "renderorder": "right-down",
+        "width": width,
+        "height": height,
+        "tilewidth": TILE_SIZE,
+        "tileheight": TILE_SIZE,
+        "infinite": false,
+        "nextlayerid": layer_id,
+        "nextobjectid": object_id,
+        "properties": tiled_json_properties(&map_properties(map)),
+        "tilesets": [{
+            "firstgid": 1,
+            "name": "codes",
+            "tilewidth": TILE_SIZE,
+            "tileheight": TILE_SIZE,
+            "tilecount": 65535,
+            "columns": 0,
+            "tiles": [],
+        }],
+        "layers": layers,
+    });
+    serde_json::to_string_pretty(&document).expect("maps serialize to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::EnemySpawn;
+
+    /// An empty room with walls all around, a door, a guard and the player.
+    fn test_map() -> Map {
+        let mut plane0 = vec![108; 64 * 64];
+        let mut plane1 = vec![0; 64 * 64];
+        for (i, word) in plane0.iter_mut().enumerate() {
+            let (x, y) = (i % 64, i / 64);
+            if y == 0 || y == 63 {
+                *word = 1;
+            }
+            if x == 0 || x == 63 {
+                *word = 2;
+            }
+        }
+        plane0[10] = 90;
+        plane1[7 * 64 + 5] = 19;
+        plane1[7 * 64 + 6] = 108;
+        Map::new(64, 64, plane0, plane1, "Test & <Map>".to_string())
+    }
+
+    #[test]
+    fn json_has_planes_tiles_and_actors() {
+        let document: Value = serde_json::from_str(&to_json(&test_map())).unwrap();
+        assert_eq!("Test & <Map>", document["name"]);
+        assert_eq!(64, document["width"]);
+        assert_eq!(64, document["plane0"].as_array().unwrap().len());
+        // rows are y, columns x
+        assert_eq!(90, document["plane0"][0][10]);
+        assert_eq!(19, document["plane1"][7][5]);
+        assert_eq!(json!({"wall": 2}), document["tiles"][5][0]);
+        assert_eq!(
+            json!({"door": {"vertical": true, "lock": 0}}),
+            document["tiles"][0][10]
+        );
+        assert_eq!(json!({"floor": {"area": 1}}), document["tiles"][5][5]);
+        assert_eq!(
+            json!([
+                {"x": 5, "y": 7, "actor": {"player": "north"}},
+                {"x": 6, "y": 7, "actor": {"enemy": {
+                    "kind": "guard",
+                    "direction": "east",
+                    "patrolling": false,
+                    "min_skill": 0,
+                }}},
+            ]),
+            document["actors"]
+        );
+        assert_eq!(
+            json!({"x": 5, "y": 7, "direction": "north"}),
+            document["player_start"]
+        );
+    }
+
+    #[test]
+    fn json_reads_back() {
+        let document: MapDocument = serde_json::from_str(&to_json(&test_map())).unwrap();
+        assert_eq!(Tile::Wall(1), document.tiles[0][5]);
+        assert_eq!(
+            Actor::Enemy(EnemySpawn::from_code(108).unwrap()),
+            document.actors[1].actor
+        );
+        assert!(document.plane2.is_none());
+        assert_eq!(Some(test_map()), document.to_map());
+    }
+
+    #[test]
+    fn plane2_is_carried_through() {
+        let plane2: Vec<u16> = (0..64 * 64).map(|i| i as u16 % 5).collect();
+        let map = test_map().with_plane2(plane2);
+
+        let json: Value = serde_json::from_str(&to_json(&map)).unwrap();
+        assert_eq!(3, json["plane2"][0][3]);
+        let document: MapDocument = serde_json::from_str(&to_json(&map)).unwrap();
+        assert_eq!(Some(map.clone()), document.to_map());
+
+        let tiled: Value = serde_json::from_str(&to_tiled_json(&map)).unwrap();
+        assert_eq!("plane2", tiled["layers"][2]["name"]);
+        assert_eq!(3, tiled["layers"][2]["data"][64 + 4]);
+    }
+
+    #[test]
+    fn mismatched_planes_are_not_a_map() {
+        let mut document = MapDocument::new(&test_map());
+        document.plane1.pop();
+        assert_eq!(None, document.to_map());
+    }
+
+    #[test]
+    fn tmx_has_layers_objects_and_properties() {
+        let tmx = to_tmx(&test_map());
+        assert!(tmx.contains("width=\"64\" height=\"64\" tilewidth=\"64\" tileheight=\"64\""));
+        assert!(tmx.contains(
+            "<property name=\"name\" type=\"string\" value=\"Test &amp; &lt;Map&gt;\"/>"
+        ));
+        assert!(
+            tmx.contains("<property name=\"player_direction\" type=\"string\" value=\"north\"/>")
+        );
+        assert!(tmx.contains("<layer id=\"1\" name=\"plane0\" width=\"64\" height=\"64\">"));
+        assert!(tmx.contains("<layer id=\"3\" name=\"walls\""));
+        assert!(tmx.contains("\n2,1,1,1,1,1,1,1,1,1,90,1,"));
+        assert!(tmx.contains(
+            "<object id=\"1\" name=\"door\" type=\"door\" x=\"640\" y=\"0\" width=\"64\" height=\"64\">"
+        ));
+        assert!(tmx.contains("<property name=\"vertical\" type=\"bool\" value=\"true\"/>"));
+        assert!(
+            tmx.contains("<object id=\"2\" name=\"player\" type=\"player\" x=\"320\" y=\"448\"")
+        );
+        assert!(tmx.contains("<property name=\"value\" type=\"string\" value=\"north\"/>"));
+        assert!(tmx.contains("nextobjectid=\"4\""));
+    }
+
+    #[test]
+    fn tiled_json_has_layers_objects_and_properties() {
+        let document: Value = serde_json::from_str(&to_tiled_json(&test_map())).unwrap();
+        assert_eq!("map", document["type"]);
+        let layers = document["layers"].as_array().unwrap();
+        let names: Vec<&str> = layers.iter().map(|l| l["name"].as_str().unwrap()).collect();
+        assert_eq!(vec!["plane0", "plane1", "walls", "doors", "actors"], names);
+        assert_eq!(90, layers[0]["data"][10]);
+        assert_eq!(108, layers[1]["data"][7 * 64 + 6]);
+        assert_eq!(0, layers[2]["data"][10]);
+        assert_eq!("enemy", layers[4]["objects"][1]["type"]);
+        assert_eq!(384, layers[4]["objects"][1]["x"]);
+        assert_eq!(
+            json!({"name": "player_x", "type": "int", "value": 5}),
+            document["properties"][1]
+        );
+    }
+}
@@ -0,0 +1,313 @@
+// Writes the assets of a data set to standard file formats, so they can be looked at
+// with regular tools: pictures, wall textures and sprites as PNG, digitized sounds as WAV.
+
+use crate::audio::{AudioSink, Sound, WavSink};
+use crate::cache::{self, Cache, CacheError, Picture, Sprite};
+use crate::constants::{SPRITE_SIZE, WALLPIC_HEIGHT, WALLPIC_WIDTH};
+use crate::ColorMap;
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ExtractError {
+    Cache(CacheError),
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExtractError::Cache(err) => write!(f, "{}", err),
+            ExtractError::Write { path, source } => {
+                write!(f, "could not write {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ExtractError::Cache(err) => Some(err),
+            ExtractError::Write { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<CacheError> for ExtractError {
+    fn from(err: CacheError) -> Self {
+        ExtractError::Cache(err)
+    }
+}
+
+/// How many files of each kind were written.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub pics: usize,
+    pub textures: usize,
+    pub sprites: usize,
+    pub sounds: usize,
+}
+
+/// Writes every asset of `cache` under `out_dir`, as `pics/TITLEPIC.png`, `textures/000.png`,
+/// `sprites/000.png` and `sounds/000.wav`. Textures and sprites missing from the data set,
+/// like the registered version ones in the shareware VSWAP, are skipped.
+pub fn extract_all(
+    cache: &Cache,
+    color_map: &ColorMap,
+    out_dir: &Path,
+) -> Result<Summary, ExtractError> {
+    let mut summary = Summary::default();
+
+    let dir = create_dir(out_dir, "pics")?;
+    for chunk in cache::STARTPICS..cache::STARTPICS + cache.num_pics() {
+        let pic = cache.get_pic(chunk)?;
+        let name = match cache.pic_name(chunk) {
+            Some(name) => name.to_string(),
+            None => format!("{:03}", chunk),
+        };
+        let pixels = pic_pixels(pic).into_iter().map(Some).collect::<Vec<_>>();
+        write_png(
+            &dir.join(format!("{}.png", name)),
+            pic.width,
+            pic.height,
+            &pixels,
+            color_map,
+        )?;
+        summary.pics += 1;
+    }
+
+    let dir = create_dir(out_dir, "textures")?;
+    for index in 0..cache.num_textures() {
+        let texture = match skip_missing(cache.get_texture(index))? {
+            Some(texture) => texture,
+            None => continue,
+        };
+        let pixels = texture_pixels(texture)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        write_png(
+            &dir.join(format!("{:03}.png", index)),
+            WALLPIC_WIDTH as u32,
+            WALLPIC_HEIGHT as u32,
+            &pixels,
+            color_map,
+        )?;
+        summary.textures += 1;
+    }
+
+    let dir = create_dir(out_dir, "sprites")?;
+    for index in 0..cache.num_sprites() {
+        let sprite = match skip_missing(cache.get_sprite(index))? {
+            Some(sprite) => sprite,
+            None => continue,
+        };
+        write_png(
+            &dir.join(format!("{:03}.png", index)),
+            SPRITE_SIZE as u32,
+            SPRITE_SIZE as u32,
+            &sprite_pixels(sprite),
+            color_map,
+        )?;
+        summary.sprites += 1;
+    }
+
+    let dir = create_dir(out_dir, "sounds")?;
+    for index in 0..cache.num_sounds() {
+        let sound = Sound::from_digi(cache.get_sound(index)?);
+        write_wav(&dir.join(format!("{:03}.wav", index)), &sound)?;
+        summary.sounds += 1;
+    }
+
+    Ok(summary)
+}
+
+/// Sparse VSWAP pages are reported as missing chunks, those are simply not there to extract.
+pub(crate) fn skip_missing<T>(result: Result<T, CacheError>) -> Result<Option<T>, CacheError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(CacheError::MissingChunk { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn create_dir(out_dir: &Path, name: &str) -> Result<PathBuf, ExtractError> {
+    let dir = out_dir.join(name);
+    fs::create_dir_all(&dir).map_err(|source| ExtractError::Write {
+        path: dir.clone(),
+        source,
+    })?;
+    Ok(dir)
+}
+
+/// Reorders the pixels of a picture, which are stored as 4 planes like the VGA memory
+/// they were meant to be copied to, into rows.
+fn pic_pixels(pic: &Picture) -> Vec<u8> {
+    let plane_width = pic.width >> 2;
+    let mut pixels = Vec::with_capacity((pic.width * pic.height) as usize);
+    for y in 0..pic.height {
+        for x in 0..pic.width {
+            let source = y * plane_width + (x >> 2) + (x & 3) * plane_width * pic.height;
+            pixels.push(pic.data[source as usize]);
+        }
+    }
+    pixels
+}
+
+/// Wall textures are stored column by column, turns them into rows.
+fn texture_pixels(texture: &[u8]) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(WALLPIC_WIDTH * WALLPIC_HEIGHT);
+    for y in 0..WALLPIC_HEIGHT {
+        for x in 0..WALLPIC_WIDTH {
+            pixels.push(texture[x * WALLPIC_HEIGHT + y]);
+        }
+    }
+    pixels
+}
+
+/// Draws a sprite into a 64x64 grid, `None` being the transparent pixels.
+fn sprite_pixels(sprite: &Sprite) -> Vec<Option<u8>> {
+    let mut pixels = vec![None; SPRITE_SIZE * SPRITE_SIZE];
+    for (column, spans) in sprite.columns.iter().enumerate() {
+        let x = sprite.left_pix as usize + column;
+        for span in spans {
+            for (row, &color) in span.pixels.iter().enumerate() {
+                pixels[(span.start as usize + row) * SPRITE_SIZE + x] = Some(color);
+            }
+        }
+    }
+    pixels
+}
+
+fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[Option<u8>],
+    color_map: &ColorMap,
+) -> Result<(), ExtractError> {
+    let write_error = |source| ExtractError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    let file = File::create(path).map_err(write_error)?;
+    encode_png(BufWriter::new(file), width, height, pixels, color_map).map_err(write_error)
+}
+
+/// Encodes 8 bit RGBA pixels, with transparent pixels where there's no color.
+fn encode_png<W: io::Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+    pixels: &[Option<u8>],
+    color_map: &ColorMap,
+) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = pixels
+        .iter()
+        .flat_map(|pixel| match pixel {
+            Some(index) => {
+                let (r, g, b) = color_map[*index as usize];
+                [r, g, b, 0xFF]
+            }
+            None => [0, 0, 0, 0],
+        })
+        .collect();
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+fn write_wav(path: &Path, sound: &Sound) -> Result<(), ExtractError> {
+    let write_error = |source| ExtractError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut sink = WavSink::create(path, sound.rate).map_err(write_error)?;
+    sink.write(&sound.samples).map_err(write_error)?;
+    sink.finish().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::Span;
+    use crate::mod_dir::tests::test_color_map;
+
+    #[test]
+    fn pic_pixels_are_unplanarized() {
+        // 8x2 picture, each plane holding 2 pixels per row
+        let pic = Picture {
+            width: 8,
+            height: 2,
+            data: vec![0, 4, 8, 12, 1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15],
+        };
+        assert_eq!(
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+            pic_pixels(&pic)
+        );
+    }
+
+    #[test]
+    fn texture_pixels_are_transposed() {
+        let texture: Vec<u8> = (0..64 * 64).map(|i| (i / 64) as u8).collect();
+        let pixels = texture_pixels(&texture);
+        assert_eq!(&(0..64).collect::<Vec<u8>>()[..], &pixels[..64]);
+        assert_eq!(&(0..64).collect::<Vec<u8>>()[..], &pixels[64 * 63..]);
+    }
+
+    #[test]
+    fn sprite_pixels_follow_spans() {
+        let sprite = Sprite {
+            left_pix: 2,
+            right_pix: 2,
+            columns: vec![vec![Span {
+                start: 3,
+                pixels: vec![0xBB, 0xCC],
+            }]],
+        };
+        let pixels = sprite_pixels(&sprite);
+        assert_eq!(Some(0xBB), pixels[3 * 64 + 2]);
+        assert_eq!(Some(0xCC), pixels[4 * 64 + 2]);
+        assert_eq!(2, pixels.iter().filter(|p| p.is_some()).count());
+    }
+
+    #[test]
+    fn png_has_palette_colors_and_transparency() {
+        let mut png_bytes = Vec::new();
+        encode_png(&mut png_bytes, 2, 1, &[Some(3), None], &test_color_map()).unwrap();
+
+        let decoder = png::Decoder::new(png_bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!((2, 1), (info.width, info.height));
+        assert_eq!(png::ColorType::Rgba, info.color_type);
+        assert_eq!(&[3, 0, 252, 255, 0, 0, 0, 0], &buffer[..8]);
+    }
+
+    #[test]
+    #[ignore]
+    fn extract_shareware_assets() {
+        let cache = cache::init(
+            Path::new("data"),
+            cache::GameVariant::Shareware,
+            cache::ChunkLayout::SHAREWARE,
+        )
+        .unwrap();
+        let out_dir = std::env::temp_dir().join("rustenstein_extract_test");
+        let summary = extract_all(&cache, &test_color_map(), &out_dir).unwrap();
+        assert_eq!(cache::NUMPICS, summary.pics);
+        assert!(out_dir.join("pics/TITLEPIC.png").is_file());
+        assert!(out_dir.join("sounds/000.wav").is_file());
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
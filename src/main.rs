@@ -1,19 +1,32 @@
 #![allow(dead_code)]
 use crate::player::{SideMovement, StraightMovement, TurnMovement};
-use cache::Picture;
-use core::slice::Iter;
+use audio::AudioSink;
+use cache::{CacheError, Font, Picture, Span, Sprite};
+use ray_caster::Surface;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::Instant;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 use minifb::{Key, KeyRepeat, Window, WindowOptions};
 
+mod audio;
 mod cache;
 type ColorMap = [(u8, u8, u8); 256];
 mod constants;
+mod door;
+mod export;
+mod extract;
 mod map;
+mod mod_dir;
+mod opl;
 mod player;
+mod push_wall;
 mod ray_caster;
+mod version;
+mod writer;
 
 use constants::*;
 
@@ -27,6 +40,17 @@ const VGA_CEILING_COLORS: [usize; 60] = [
 
 const DARKNESS: f64 = 0.75;
 
+const BOSS_LEVEL: usize = 8;
+const SECRET_LEVEL: usize = 9;
+/// The level each episode goes on with after its secret level.
+const ELEVATOR_BACK_TO: [usize; 6] = [1, 1, 7, 3, 5, 3];
+/// Spear of Destiny has a single episode with two secret levels, each reached from the
+/// secret exit of a level and leading back to the level after that one.
+const SPEAR_SECRET_LEVELS: [(usize, usize); 2] = [(3, 18), (11, 19)];
+/// The Death Knight's level skips the secret levels, on to the last one.
+const SPEAR_DEATH_KNIGHT_LEVEL: usize = 17;
+const SPEAR_LAST_LEVEL: usize = 20;
+
 /// Run Wolfenstein 3D
 #[derive(Parser, Debug)]
 struct Opts {
@@ -41,6 +65,50 @@ struct Opts {
     /// Level to load. Only the shareware episode levels are supported for now.
     #[clap(short, long, default_value="1", possible_values=["1","2","3","4","5","6","7","8","9","10"])]
     level: usize,
+
+    /// Directory to read the game data files from.
+    #[clap(long, default_value = "data", parse(from_os_str))]
+    data_dir: PathBuf,
+
+    /// Directory with loose files that replace assets of the data files, e.g.
+    /// textures/012.png, sprites/209.png, pics/TITLEPIC.png or maps/E1M1.json
+    #[clap(long, parse(from_os_str))]
+    mod_dir: Option<PathBuf>,
+
+    /// Write the game audio to this WAV file, e.g. to check sounds without a sound card.
+    #[clap(long, parse(from_os_str))]
+    wav_out: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write the pictures, wall textures and sprites as PNG and the digitized sounds as WAV
+    Extract {
+        /// Directory to write the files to
+        #[clap(parse(from_os_str))]
+        out_dir: PathBuf,
+    },
+    /// Write every map as plain JSON, or as a Tiled map with tmx and tmj
+    ExportMaps {
+        /// Directory to write the files to
+        #[clap(parse(from_os_str))]
+        out_dir: PathBuf,
+
+        #[clap(long, default_value = "json", possible_values = ["json", "tmx", "tmj"])]
+        format: export::Format,
+    },
+    /// Tell which release the data files are from, with the size and checksum of each file
+    Info,
+    /// Write a VSWAP file with the textures, sprites and sounds of the data files, the PNG
+    /// files of the mod directory replacing theirs
+    BuildVswap {
+        /// File to write
+        #[clap(parse(from_os_str))]
+        out_file: PathBuf,
+    },
 }
 
 struct Video {
@@ -57,15 +125,135 @@ struct Video {
 struct Game {
     player: player::Player,
     map: map::Map,
+    doors: door::Doors,
+    /// The enemies of the level at the chosen difficulty, where they start.
+    enemies: Vec<(usize, usize, map::EnemySpawn)>,
+    /// The static objects of the level that are still there.
+    statics: Vec<(usize, usize, map::Static)>,
+    /// The secret wall that's moving, if one was pushed and didn't stop yet.
+    push_wall: Option<push_wall::PushWall>,
+    /// How many secret walls the player pushed in the level.
+    secrets_found: usize,
+    variant: cache::GameVariant,
     episode: usize,
     level: usize,
+    difficulty: usize,
     start_time: Instant,
+    /// When the doors and the rest of the world last moved along.
+    last_update: Instant,
     cache: cache::Cache,
+    mixer: audio::Mixer,
+    audio_frames: usize,
 }
 
 pub fn main() {
     let args = Opts::parse();
-    let mut game = Game::new(args.level);
+    let variant = cache::detect_variant(&args.data_dir).unwrap_or_else(|| {
+        eprintln!(
+            "No Wolfenstein 3D data files found in {}",
+            args.data_dir.display()
+        );
+        process::exit(1);
+    });
+
+    let mut fingerprint =
+        version::fingerprint(&args.data_dir, variant).unwrap_or_else(|err| fail(err));
+
+    // the tools work with any chunk layout, the game needs a release it knows the chunks of
+    match &args.command {
+        Some(Command::Info) => {
+            fingerprint.checksum().unwrap_or_else(|err| fail(err));
+            print_info(&args.data_dir, &fingerprint);
+            return;
+        }
+        Some(Command::Extract { out_dir }) => {
+            let cache = open_cache(
+                &args.data_dir,
+                variant,
+                fingerprint.layout(),
+                args.mod_dir.as_deref(),
+            )
+            .unwrap_or_else(|err| fail(err));
+            match extract::extract_all(&cache, &build_color_map(), out_dir) {
+                Ok(summary) => println!(
+                    "Wrote {} pictures, {} textures, {} sprites and {} sounds to {}",
+                    summary.pics,
+                    summary.textures,
+                    summary.sprites,
+                    summary.sounds,
+                    out_dir.display()
+                ),
+                Err(err) => {
+                    eprintln!("Could not extract the game data: {}", err);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::ExportMaps { out_dir, format }) => {
+            let cache = open_cache(
+                &args.data_dir,
+                variant,
+                fingerprint.layout(),
+                args.mod_dir.as_deref(),
+            )
+            .unwrap_or_else(|err| fail(err));
+            match export::export_all(&cache, out_dir, *format) {
+                Ok(count) => println!("Wrote {} maps to {}", count, out_dir.display()),
+                Err(err) => {
+                    eprintln!("Could not export the maps: {}", err);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::BuildVswap { out_file }) => {
+            let cache = open_cache(
+                &args.data_dir,
+                variant,
+                fingerprint.layout(),
+                args.mod_dir.as_deref(),
+            )
+            .unwrap_or_else(|err| fail(err));
+            let contents =
+                writer::VswapContents::from_cache(&cache).unwrap_or_else(|err| fail(err));
+            match writer::write_vswap(&contents, out_file) {
+                Ok(()) => println!(
+                    "Wrote {} textures, {} sprites and {} sounds to {}",
+                    contents.textures.iter().flatten().count(),
+                    contents.sprites.iter().flatten().count(),
+                    contents.sounds.len(),
+                    out_file.display()
+                ),
+                Err(err) => {
+                    eprintln!("Could not write {}: {}", out_file.display(), err);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        None => {}
+    }
+
+    let release = fingerprint.compatible_release().unwrap_or_else(|err| {
+        eprintln!("Could not load the game data: {}", err);
+        process::exit(1);
+    });
+    let mut game = Game::new(
+        &args.data_dir,
+        release,
+        args.mod_dir.as_deref(),
+        args.level,
+        args.dificulty,
+    )
+    .unwrap_or_else(|err| fail(err));
+    let mut audio_sink: Option<Box<dyn AudioSink>> = args.wav_out.as_ref().map(|path| {
+        let sink = audio::WavSink::create(path, audio::OUTPUT_RATE).unwrap_or_else(|err| {
+            eprintln!("Could not create {}: {}", path.display(), err);
+            process::exit(1);
+        });
+        Box::new(sink) as Box<dyn AudioSink>
+    });
     let mut video = Video::new(args.scale);
     let mut window = Window::new(
         "rustenstein 3D",
@@ -78,22 +266,198 @@ pub fn main() {
     // Limit to max ~60 fps update rate
     window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
 
-    show_title(&game, &mut video, &mut window);
-    let map = &game.map;
-
-    while process_input(&window, &mut game.player, map).is_ok() {
-        draw_world(&game, &mut video);
-        draw_weapon(&game, &mut video);
-        draw_status(&game, &mut video);
+    show_title(&game, &mut video, &mut window).unwrap_or_else(|err| fail(err));
+    while process_input(&window, &mut game.player, &game.map, &game.doors).is_ok() {
+        if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            use_facing_tile(&mut game).unwrap_or_else(|err| fail(err));
+        }
+        update_world(&mut game);
+        draw_world(&game, &mut video).unwrap_or_else(|err| fail(err));
+        draw_weapon(&game, &mut video).unwrap_or_else(|err| fail(err));
+        draw_status(&game, &mut video).unwrap_or_else(|err| fail(err));
 
         video.present(&mut window);
+        update_audio(&mut game, &mut audio_sink).unwrap_or_else(|err| {
+            eprintln!("Could not write the game audio: {}", err);
+            process::exit(1);
+        });
+    }
+
+    if let Some(sink) = audio_sink.as_mut() {
+        if let Err(err) = sink.finish() {
+            eprintln!("Could not write the game audio: {}", err);
+        }
+    }
+}
+
+/// Moves the doors and the pushed wall along for the time elapsed since the last frame, and
+/// picks up the keys the player walked over.
+fn update_world(game: &mut Game) {
+    let now = Instant::now();
+    let tics = (now - game.last_update).as_secs_f64() * door::TICS_PER_SECOND;
+    game.last_update = now;
+    let tile = game.player.tile();
+    game.doors.update(tics, tile);
+    if let Some(wall) = game.push_wall.as_mut() {
+        if !wall.update(&mut game.map, tics) {
+            game.push_wall = None;
+        }
+    }
+
+    let keys = &mut game.player.keys;
+    game.statics.retain(|&(x, y, object)| {
+        let picked_up = (x, y) == tile && object.bonus().is_some_and(|bonus| keys.pick_up(bonus));
+        !picked_up
+    });
+}
+
+/// Acts on the tile the player is facing: opens or closes a door, if the player has the
+/// key to it, pushes a secret wall, or takes the elevator to the next level.
+fn use_facing_tile(game: &mut Game) -> Result<(), CacheError> {
+    let (x, y) = game.player.facing_tile();
+    if game.map.actor_at(x, y) == Some(map::Actor::PushWall) {
+        // only one wall moves at a time
+        if game.push_wall.is_none() {
+            game.push_wall = push_wall::PushWall::push(&mut game.map, x, y, game.player.facing());
+            if game.push_wall.is_some() {
+                game.secrets_found += 1;
+                play_digi(game, audio::PUSH_WALL_DIGI)?;
+            }
+        }
+        return Ok(());
+    }
+    if game.map.tile_at(x, y) == map::Tile::Elevator {
+        let (px, py) = game.player.tile();
+        let secret_exit = game.map.is_secret_exit(px, py);
+        if let Some(level) = next_level(game.variant, game.episode, game.level, secret_exit) {
+            game.start_level(level)?;
+        }
+        return Ok(());
+    }
+    if game.doors.operate(x, y, game.player.keys) {
+        let opening = game
+            .doors
+            .door_at(x, y)
+            .is_some_and(|door| door.state == door::DoorState::Opening);
+        let sound = if opening {
+            audio::OPEN_DOOR_DIGI
+        } else {
+            audio::CLOSE_DOOR_DIGI
+        };
+        play_digi(game, sound)?;
+    }
+    Ok(())
+}
+
+/// Plays a digitized sound, if the data files have it.
+fn play_digi(game: &mut Game, index: usize) -> Result<(), CacheError> {
+    let sound = match game.cache.get_sound(index) {
+        Ok(sound) => audio::Sound::from_digi(sound),
+        Err(CacheError::MissingSound { .. }) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    game.mixer.play(audio::Channel::Digitized, sound, false);
+    Ok(())
+}
+
+/// The level an elevator leads to. The secret level is reached from the secret exit of
+/// one level, and leads back to the level after it. The boss level ends the episode.
+fn next_level(
+    variant: cache::GameVariant,
+    episode: usize,
+    level: usize,
+    secret_exit: bool,
+) -> Option<usize> {
+    if variant == cache::GameVariant::SpearOfDestiny {
+        return next_spear_level(level, secret_exit);
+    }
+    match level {
+        SECRET_LEVEL => Some(ELEVATOR_BACK_TO[episode]),
+        BOSS_LEVEL => None,
+        _ if secret_exit => Some(SECRET_LEVEL),
+        _ => Some(level + 1),
     }
 }
 
+/// The level an elevator leads to in Spear of Destiny, which ends after the last level.
+fn next_spear_level(level: usize, secret_exit: bool) -> Option<usize> {
+    for (from, secret) in SPEAR_SECRET_LEVELS {
+        if level == secret {
+            return Some(from + 1);
+        }
+        if level == from && secret_exit {
+            return Some(secret);
+        }
+    }
+    match level {
+        SPEAR_LAST_LEVEL => None,
+        SPEAR_DEATH_KNIGHT_LEVEL => Some(SPEAR_LAST_LEVEL),
+        _ => Some(level + 1),
+    }
+}
+
+/// Mixes the audio due since the last frame and sends it to the sink, if there's one.
+fn update_audio(game: &mut Game, sink: &mut Option<Box<dyn AudioSink>>) -> io::Result<()> {
+    let due = (game.start_time.elapsed().as_secs_f64() * game.mixer.rate() as f64) as usize;
+    let frames = due.saturating_sub(game.audio_frames);
+    game.audio_frames += frames;
+
+    match sink {
+        Some(sink) => game.mixer.render(sink.as_mut(), frames),
+        None => {
+            game.mixer.mix(frames);
+            Ok(())
+        }
+    }
+}
+
+fn print_info(data_dir: &Path, fingerprint: &version::Fingerprint) {
+    println!("Data directory: {}", data_dir.display());
+    match (fingerprint.identify(), fingerprint.compatible_release()) {
+        (Some(release), _) => println!("Release: {}", release.name),
+        (None, Ok(release)) => println!("Release: unknown, laid out like {}", release.name),
+        (None, Err(err)) => println!("Release: {}", err),
+    }
+    println!(
+        "Graphics: {} chunks, {} pictures",
+        fingerprint.graphics_chunks, fingerprint.num_pics
+    );
+    for file in &fingerprint.files {
+        let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+        println!(
+            "  {:<14} {:>8} bytes  crc32 {:08x}",
+            name,
+            file.size,
+            file.crc32.unwrap_or_default()
+        );
+    }
+}
+
+/// Opens the data files, with the assets of the mod directory replacing theirs if given.
+fn open_cache(
+    data_dir: &Path,
+    variant: cache::GameVariant,
+    layout: cache::ChunkLayout,
+    mod_dir: Option<&Path>,
+) -> Result<cache::Cache, CacheError> {
+    let cache = cache::init(data_dir, variant, layout)?;
+    Ok(match mod_dir {
+        Some(dir) => cache.with_mod_dir(mod_dir::ModDir::new(dir, build_color_map())),
+        None => cache,
+    })
+}
+
+/// Reports a problem with the game data files and quits.
+fn fail(err: cache::CacheError) -> ! {
+    eprintln!("Could not load the game data: {}", err);
+    process::exit(1);
+}
+
 fn process_input(
     window: &Window,
     player: &mut player::Player,
     map: &map::Map,
+    doors: &door::Doors,
 ) -> Result<(), String> {
     if !window.is_open() || window.is_key_pressed(Key::Escape, KeyRepeat::No) {
         return Err(String::from("Goodbye!"));
@@ -141,25 +505,32 @@ fn process_input(
         side = Some(SideMovement::StrafeRight);
     }
 
-    player.walk(map, straight, side, turn, run);
+    player.walk(map, doors, straight, side, turn, run);
 
     Ok(())
 }
 
-fn show_title(game: &Game, video: &mut Video, window: &mut Window) {
-    let titlepic = game.cache.get_pic(cache::TITLEPIC);
+fn show_title(game: &Game, video: &mut Video, window: &mut Window) -> Result<(), CacheError> {
+    let titlepic = game.cache.get_pic(cache::TITLEPIC)?;
     video.draw_texture(0, 0, titlepic);
 
     // wait for input
     while window.get_keys_pressed(KeyRepeat::No).is_empty() {
         video.present(window);
     }
+    Ok(())
 }
 
-fn draw_world(game: &Game, video: &mut Video) {
+fn draw_world(game: &Game, video: &mut Video) -> Result<(), CacheError> {
     // TODO consider passing game as param here
-    let ray_hits =
-        ray_caster::draw_rays(video.pix_width, video.pix_height, &game.map, &game.player);
+    let ray_hits = ray_caster::draw_rays(
+        video.pix_width,
+        video.pix_height,
+        &game.map,
+        &game.doors,
+        game.push_wall.as_ref(),
+        &game.player,
+    );
 
     // draw floor and ceiling
     for x in 0..video.pix_width {
@@ -174,14 +545,17 @@ fn draw_world(game: &Game, video: &mut Video) {
     for x in 0..video.pix_width {
         let hit = &ray_hits[x as usize];
 
-        // convert tile number to wall pic
-        // accept-the-mystery
-        let wallpic = if hit.horizontal {
-            (hit.tile - 1) * 2
-        } else {
-            (hit.tile - 1) * 2 + 1
+        let wallpic = match hit.surface {
+            // convert tile number to wall pic
+            // accept-the-mystery
+            Surface::Wall(tile) if hit.horizontal => (tile as usize - 1) * 2,
+            Surface::Wall(tile) => (tile as usize - 1) * 2 + 1,
+            Surface::Door { x, y } => match game.doors.door_at(x, y) {
+                Some(door) => door.texture(game.cache.num_textures()),
+                None => continue,
+            },
         };
-        let texture = game.cache.get_texture(wallpic as usize);
+        let texture = game.cache.get_texture(wallpic)?;
 
         let current = ray_hits[x as usize].height as i32;
 
@@ -205,52 +579,92 @@ fn draw_world(game: &Game, video: &mut Video) {
             ytex += step;
         }
     }
+    Ok(())
 }
 
-fn draw_weapon(game: &Game, video: &mut Video) {
-    // FIXME use a constant for that 209
-    let (weapon_shape, weapon_data) = game.cache.get_sprite(209);
-
-    // TODO pass the shape num instead of pieces of the shape
-    video.simple_scale_shape(
-        weapon_shape.left_pix,
-        weapon_shape.right_pix,
-        &weapon_shape.dataofs,
-        weapon_data,
-    );
+fn draw_weapon(game: &Game, video: &mut Video) -> Result<(), CacheError> {
+    let weapon = game.cache.get_sprite(cache::SPR_PISTOLREADY)?;
+    video.simple_scale_shape(weapon);
+    Ok(())
 }
 
-fn draw_status(game: &Game, video: &mut Video) {
-    let statuspic = game.cache.get_pic(cache::STATUSBARPIC);
+fn draw_status(game: &Game, video: &mut Video) -> Result<(), CacheError> {
+    let statuspic = game.cache.get_pic(cache::STATUSBARPIC)?;
     video.draw_texture(0, video.pix_height, statuspic);
 
     let facepic = match game.start_time.elapsed().as_secs() % 3 {
-        0 => game.cache.get_pic(cache::FACE1APIC),
-        1 => game.cache.get_pic(cache::FACE1BPIC),
-        2 => game.cache.get_pic(cache::FACE1CPIC),
+        0 => game.cache.get_pic(cache::FACE1APIC)?,
+        1 => game.cache.get_pic(cache::FACE1BPIC)?,
+        2 => game.cache.get_pic(cache::FACE1CPIC)?,
         _ => unreachable!(),
     };
 
     let shift_x = video.pix_width / 2 - facepic.width * video.scale;
     let shift_y = video.pix_height + facepic.height * video.scale / 8;
     video.draw_texture(shift_x, shift_y, facepic);
+
+    let keys = game.player.keys;
+    for (y, has_key, keypic) in [
+        (4, keys.gold, cache::GOLDKEYPIC),
+        (20, keys.silver, cache::SILVERKEYPIC),
+    ] {
+        let pic = game
+            .cache
+            .get_pic(if has_key { keypic } else { cache::NOKEYPIC })?;
+        video.draw_texture(240 * video.scale, video.pix_height + y * video.scale, pic);
+    }
+    Ok(())
 }
 
 impl Game {
-    pub fn new(level: usize) -> Self {
+    pub fn new(
+        data_dir: &Path,
+        release: &version::Release,
+        mod_dir: Option<&Path>,
+        level: usize,
+        difficulty: usize,
+    ) -> Result<Self, CacheError> {
         let level = level - 1;
-        let cache = cache::init();
-        let map = cache.get_map(0, level);
+        let cache = open_cache(data_dir, release.variant, release.layout, mod_dir)?;
+        let map = cache.get_map(0, level)?;
         let player = map.find_player();
-        Self {
+        let enemies = map.enemy_spawns(difficulty);
+        let statics = map.statics();
+        let doors = door::Doors::new(&map);
+        Ok(Self {
             cache,
             map,
+            doors,
             player,
+            enemies,
+            statics,
+            push_wall: None,
+            secrets_found: 0,
+            variant: release.variant,
             // we only support episode 0 for now -- the shareware one
             episode: 0,
             level,
+            difficulty,
             start_time: Instant::now(),
-        }
+            last_update: Instant::now(),
+            mixer: audio::Mixer::new(audio::OUTPUT_RATE),
+            audio_frames: 0,
+        })
+    }
+
+    /// Starts a level of the episode from the player start, with the doors closed and
+    /// without the keys picked up in the previous level.
+    pub fn start_level(&mut self, level: usize) -> Result<(), CacheError> {
+        let map = self.cache.get_map(self.episode, level)?;
+        self.player = map.find_player();
+        self.doors = door::Doors::new(&map);
+        self.enemies = map.enemy_spawns(self.difficulty);
+        self.statics = map.statics();
+        self.push_wall = None;
+        self.secrets_found = 0;
+        self.map = map;
+        self.level = level;
+        Ok(())
     }
 }
 
@@ -277,7 +691,7 @@ impl Video {
 
     pub fn put_pixel(&mut self, x: u32, y: u32, color_index: usize) {
         let offset = (y * self.width + x) as usize;
-        let (r, g, b) = self.color_map[color_index as usize];
+        let (r, g, b) = self.color_map[color_index];
         let (r, g, b) = (r as u32, g as u32, b as u32);
 
         // convert rgb to u32
@@ -286,7 +700,7 @@ impl Video {
 
     pub fn put_darkened_pixel(&mut self, x: u32, y: u32, color_index: usize, lightness: u32) {
         let offset = (y * self.width + x) as usize;
-        let (r, g, b) = self.color_map[color_index as usize];
+        let (r, g, b) = self.color_map[color_index];
 
         // apply a darkness factor based on distance from the center
         let factor =
@@ -324,13 +738,36 @@ impl Video {
         }
     }
 
-    fn simple_scale_shape(
-        &mut self,
-        left_pix: u16,
-        right_pix: u16,
-        dataofs: &[u16],
-        shape_bytes: &[u8],
-    ) {
+    /// Draws `text` with its top left corner at `x`, `y`, each pixel of the font being
+    /// a `scale` sized square of `color`. Whatever falls off the screen is clipped.
+    pub fn draw_text(&mut self, x: u32, y: u32, text: &str, font: &Font, color: usize, scale: u32) {
+        let mut x = x;
+        for c in text.chars() {
+            let (width, glyph) = font.glyph(c);
+            for (i, &pixel) in glyph.iter().enumerate() {
+                if pixel == 0 {
+                    continue;
+                }
+                let glyph_x = x + (i % width as usize) as u32 * scale;
+                let glyph_y = y + (i / width as usize) as u32 * scale;
+                for j in 0..scale {
+                    for k in 0..scale {
+                        if glyph_x + k < self.width && glyph_y + j < self.height {
+                            self.put_pixel(glyph_x + k, glyph_y + j, color);
+                        }
+                    }
+                }
+            }
+            x += width as u32 * scale;
+        }
+    }
+
+    /// Width in screen pixels of `text` when drawn by `draw_text`, e.g. to center it.
+    pub fn text_width(&self, text: &str, font: &Font, scale: u32) -> u32 {
+        font.measure(text) * scale
+    }
+
+    fn simple_scale_shape(&mut self, sprite: &Sprite) {
         let sprite_scale_factor = 2;
         let xcenter = self.pix_width / 2;
         let height = self.pix_height + 1;
@@ -339,15 +776,11 @@ impl Video {
         let pixheight = scale * sprite_scale_factor;
         let actx = xcenter - scale;
         let upperedge = self.pix_height / 2 - scale;
-        // cmdptr=(word *) shape->dataofs;
-        // cmdptr = iter(shape.dataofs)
-        let mut cmdptr = dataofs.iter();
 
-        let mut i = left_pix;
-        let mut pixcnt = i as u32 * pixheight;
+        let mut pixcnt = sprite.left_pix as u32 * pixheight;
         let mut rpix = (pixcnt >> 6) + actx;
 
-        while i <= right_pix {
+        for spans in &sprite.columns {
             let mut lpix = rpix;
             if lpix >= self.pix_width {
                 break;
@@ -356,64 +789,53 @@ impl Video {
             pixcnt += pixheight;
             rpix = (pixcnt >> 6) + actx;
 
-            if lpix != rpix && rpix > 0 {
-                if rpix > self.pix_width {
-                    rpix = self.pix_width;
-                    i = right_pix + 1;
-                }
-                let read_word = |line: &mut Iter<u8>| {
-                    u16::from_le_bytes([*line.next().unwrap_or(&0), *line.next().unwrap_or(&0)])
-                };
-                let read_word_signed = |line: &mut Iter<u8>| {
-                    i16::from_le_bytes([*line.next().unwrap_or(&0), *line.next().unwrap_or(&0)])
-                };
-
-                let cline = &shape_bytes[*cmdptr.next().unwrap() as usize..];
-                while lpix < rpix {
-                    let mut line = cline.iter();
-                    let mut endy = read_word(&mut line);
-                    while endy > 0 {
-                        endy >>= 1;
-                        let newstart = read_word_signed(&mut line);
-                        let starty = read_word(&mut line) >> 1;
-                        let mut j = starty;
-                        let mut ycnt = j as u32 * pixheight;
-                        let mut screndy: i32 = (ycnt >> 6) as i32 + upperedge as i32;
-
-                        let mut pixy = screndy as u32;
-                        while j < endy {
-                            let mut scrstarty = screndy;
-                            ycnt += pixheight;
-                            screndy = (ycnt >> 6) as i32 + upperedge as i32;
-                            if scrstarty != screndy && screndy > 0 {
-                                let index = newstart + j as i16;
-                                let col = if index >= 0 {
-                                    shape_bytes[index as usize]
-                                } else {
-                                    0
-                                };
-                                if scrstarty < 0 {
-                                    scrstarty = 0;
-                                }
-                                if screndy > self.pix_height as i32 {
-                                    screndy = self.pix_height as i32;
-                                    j = endy;
-                                }
-
-                                while scrstarty < screndy {
-                                    self.put_pixel(lpix, pixy, col as usize);
-                                    pixy += 1;
-                                    scrstarty += 1;
-                                }
-                            }
-                            j += 1;
-                        }
-                        endy = read_word(&mut line);
-                    }
-                    lpix += 1;
+            if lpix == rpix || rpix == 0 {
+                continue;
+            }
+            let clipped = rpix > self.pix_width;
+            if clipped {
+                rpix = self.pix_width;
+            }
+            while lpix < rpix {
+                for span in spans {
+                    self.scale_span(lpix, span, pixheight, upperedge);
                 }
+                lpix += 1;
+            }
+            if clipped {
+                break;
+            }
+        }
+    }
+
+    /// Draws a span of a sprite column scaled up to `pixheight` / 64.
+    fn scale_span(&mut self, x: u32, span: &Span, pixheight: u32, upperedge: u32) {
+        let mut ycnt = span.start as u32 * pixheight;
+        let mut screndy = (ycnt >> 6) as i32 + upperedge as i32;
+        let mut pixy = screndy as u32;
+
+        for &color in &span.pixels {
+            let mut scrstarty = screndy;
+            ycnt += pixheight;
+            screndy = (ycnt >> 6) as i32 + upperedge as i32;
+            if scrstarty == screndy || screndy <= 0 {
+                continue;
+            }
+            if scrstarty < 0 {
+                scrstarty = 0;
+            }
+            let clipped = screndy > self.pix_height as i32;
+            if clipped {
+                screndy = self.pix_height as i32;
+            }
+            while scrstarty < screndy {
+                self.put_pixel(x, pixy, color as usize);
+                pixy += 1;
+                scrstarty += 1;
+            }
+            if clipped {
+                break;
             }
-            i += 1;
         }
     }
 }
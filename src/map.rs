@@ -1,14 +1,61 @@
+use crate::cache::SPR_STAT_0;
 use crate::constants::*;
 use crate::player;
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::ops::RangeInclusive;
 
-#[derive(Copy, Clone)]
+// The codes of plane 0, as the original levels use them. Codes below `AMBUSH_TILE` that
+// aren't doors are solid walls, the code picking the texture, and the floor tiles from
+// `AREA_TILE` on tell which area they're in, for sound to travel between connected areas.
+
+/// Walls go from code 1 to this one, each code picking a pair of textures, lit and shaded.
+pub const LAST_WALL_TILE: u16 = 63;
+/// The wall with the elevator switch, which ends the level.
+pub const ELEVATOR_TILE: u16 = 21;
+/// The elevator switch once it's thrown, which looks like one but does nothing.
+pub const FAKE_ELEVATOR_TILE: u16 = 22;
+/// Doors come in pairs, vertical on the even code and horizontal on the odd one: normal
+/// doors, then the 4 locked ones and the elevator door, so the lock is (code - 90) / 2.
+pub const FIRST_DOOR_TILE: u16 = 90;
+pub const LAST_DOOR_TILE: u16 = 101;
+/// The codes left between the walls, the doors and `AMBUSH_TILE`. They have no texture,
+/// but the original engine makes them solid all the same, so they're dead ends that are
+/// kept as walls.
+pub const DEAD_END_TILES: [RangeInclusive<u16>; 2] = [
+    LAST_WALL_TILE + 1..=FIRST_DOOR_TILE - 1,
+    LAST_DOOR_TILE + 1..=AMBUSH_TILE - 1,
+];
+/// A floor tile where enemies wait for the player instead of reacting to noise. It takes
+/// the area of the floor next to it.
+pub const AMBUSH_TILE: u16 = 106;
+/// The first of the `NUM_AREAS` floor area codes.
+pub const AREA_TILE: u16 = 107;
+pub const NUM_AREAS: u16 = 37;
+/// The floor in front of an elevator switch that leads to the secret level instead of the
+/// next one. It's the first area code too, so it's also floor of area 0.
+pub const ALT_ELEVATOR_TILE: u16 = 107;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Tile {
-    Floor,
+    Floor { area: u8 },
+    AmbushFloor,
     Wall(u16),
+    Elevator,
+    FakeElevator,
     Door { vertical: bool, lock: u16 },
 }
 
+impl Tile {
+    /// Whether the tile stops the player and the rays, doors aside.
+    pub fn is_solid(&self) -> bool {
+        matches!(self, Tile::Wall(_) | Tile::Elevator | Tile::FakeElevator)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Direction {
     North,
     East,
@@ -16,62 +63,422 @@ pub enum Direction {
     West,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Actor {
     Player(Direction),
-    Enemy, // TODO differentiate enemy types
-    Item,  // TODO differentiate item types
+    Enemy(EnemySpawn),
+    Static(Static),
     DeadGuard,
     PushWall,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Enemy {
+    Guard,
+    Officer,
+    Ss,
+    Dog,
+    Mutant,
+    Hans,
+    Gretel,
+    Giftmacher,
+    Fettgesicht,
+    Schabbs,
+    FakeHitler,
+    Hitler,
+    Blinky,
+    Clyde,
+    Pinky,
+    Inky,
+}
+
+/// An enemy placed on plane 1: who, which way it faces, and the lowest skill level,
+/// 0 to 3 like `--dificulty`, it shows up at.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnemySpawn {
+    pub kind: Enemy,
+    /// Bosses and ghosts don't face any particular way.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+    /// Whether it walks along the path arrows instead of standing guard.
+    pub patrolling: bool,
+    pub min_skill: usize,
+}
+
+/// Plane 1 codes of the enemies that show up at every skill level. Each kind has 4 codes
+/// standing and 4 patrolling, one per direction: east, north, west and south.
+const ENEMY_CODES: [(u16, Enemy); 5] = [
+    (108, Enemy::Guard),
+    (116, Enemy::Officer),
+    (126, Enemy::Ss),
+    (134, Enemy::Dog),
+    (216, Enemy::Mutant),
+];
+/// How far the codes of the enemies that only show up from the medium skill level are from
+/// the ones above, and twice that for the hard skill level. Mutants, added later, have
+/// their own.
+const MEDIUM_SKILL_OFFSET: u16 = 36;
+const MUTANT_SKILL_OFFSET: u16 = 18;
+const MEDIUM_SKILL: usize = 2;
+const HARD_SKILL: usize = 3;
+
+/// Plane 1 codes of the bosses and of the ghosts of the secret level.
+const BOSS_CODES: [(u16, Enemy); 11] = [
+    (214, Enemy::Hans),
+    (197, Enemy::Gretel),
+    (215, Enemy::Giftmacher),
+    (179, Enemy::Fettgesicht),
+    (196, Enemy::Schabbs),
+    (160, Enemy::FakeHitler),
+    (178, Enemy::Hitler),
+    (224, Enemy::Blinky),
+    (225, Enemy::Clyde),
+    (226, Enemy::Pinky),
+    (227, Enemy::Inky),
+];
+
+impl EnemySpawn {
+    /// The enemy of a plane 1 code, if it's one.
+    pub fn from_code(code: u16) -> Option<EnemySpawn> {
+        if let Some(&(_, kind)) = BOSS_CODES.iter().find(|&&(boss, _)| boss == code) {
+            return Some(EnemySpawn {
+                kind,
+                direction: None,
+                patrolling: false,
+                min_skill: 0,
+            });
+        }
+
+        ENEMY_CODES.iter().find_map(|&(base, kind)| {
+            let skill_offset = match kind {
+                Enemy::Mutant => MUTANT_SKILL_OFFSET,
+                _ => MEDIUM_SKILL_OFFSET,
+            };
+            [
+                (0, 0),
+                (skill_offset, MEDIUM_SKILL),
+                (skill_offset * 2, HARD_SKILL),
+            ]
+            .iter()
+            .find_map(|&(offset, min_skill)| {
+                let index = code.checked_sub(base + offset).filter(|&i| i < 8)?;
+                let direction = match index % 4 {
+                    0 => Direction::East,
+                    1 => Direction::North,
+                    2 => Direction::West,
+                    _ => Direction::South,
+                };
+                Some(EnemySpawn {
+                    kind,
+                    direction: Some(direction),
+                    patrolling: index >= 4,
+                    min_skill,
+                })
+            })
+        })
+    }
+}
+
+/// The plane 1 code that makes the wall on its tile a secret push wall.
+pub const PUSH_WALL_CODE: u16 = 98;
+
+/// The plane 1 code of the first static object, the others follow in the order of `Static`.
+pub const FIRST_STATIC_CODE: u16 = 23;
+
+/// The static objects placed on plane 1, named after the comments of the original
+/// `statinfo` table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Static {
+    Puddle,
+    GreenBarrel,
+    TableAndChairs,
+    FloorLamp,
+    Chandelier,
+    HangedMan,
+    DogFood,
+    RedPillar,
+    Tree,
+    SkeletonFlat,
+    Sink,
+    PottedPlant,
+    Urn,
+    BareTable,
+    CeilingLight,
+    KitchenStuff,
+    SuitOfArmor,
+    HangingCage,
+    SkeletonInCage,
+    SkeletonRelax,
+    GoldKey,
+    SilverKey,
+    Bed,
+    Basket,
+    Food,
+    FirstAid,
+    Clip,
+    MachineGun,
+    ChainGun,
+    Cross,
+    Chalice,
+    Chest,
+    Crown,
+    OneUp,
+    Gibs,
+    Barrel,
+    Well,
+    EmptyWell,
+    Gibs2,
+    Flag,
+    CallApogee,
+    Junk1,
+    Junk2,
+    Junk3,
+    Pots,
+    Stove,
+    Spears,
+    Vines,
+    /// The clip enemies drop, worth half a clip. It follows the others in the table but
+    /// the original levels don't place any.
+    DroppedClip,
+}
+
+/// What picking up a static object gives.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bonus {
+    GoldKey,
+    SilverKey,
+    /// 4 health.
+    DogFood,
+    /// 10 health.
+    Food,
+    /// 25 health.
+    FirstAid,
+    /// 8 bullets.
+    Clip,
+    /// 4 bullets.
+    HalfClip,
+    MachineGun,
+    ChainGun,
+    /// 100 points.
+    Cross,
+    /// 500 points.
+    Chalice,
+    /// 1000 points.
+    Chest,
+    /// 5000 points.
+    Crown,
+    /// Full health, 25 bullets and an extra life.
+    FullHeal,
+    /// 1 health, only when there's 10 or less left.
+    Gibs,
+}
+
+/// Sprite offset from `SPR_STAT_0`, whether it blocks the way and its bonus, for every
+/// static object in the order of `Static`.
+const STATIC_INFO: [(Static, usize, bool, Option<Bonus>); 49] = [
+    (Static::Puddle, 0, false, None),
+    (Static::GreenBarrel, 1, true, None),
+    (Static::TableAndChairs, 2, true, None),
+    (Static::FloorLamp, 3, true, None),
+    (Static::Chandelier, 4, false, None),
+    (Static::HangedMan, 5, true, None),
+    (Static::DogFood, 6, false, Some(Bonus::DogFood)),
+    (Static::RedPillar, 7, true, None),
+    (Static::Tree, 8, true, None),
+    (Static::SkeletonFlat, 9, false, None),
+    (Static::Sink, 10, true, None),
+    (Static::PottedPlant, 11, true, None),
+    (Static::Urn, 12, true, None),
+    (Static::BareTable, 13, true, None),
+    (Static::CeilingLight, 14, false, None),
+    (Static::KitchenStuff, 15, false, None),
+    (Static::SuitOfArmor, 16, true, None),
+    (Static::HangingCage, 17, true, None),
+    (Static::SkeletonInCage, 18, true, None),
+    (Static::SkeletonRelax, 19, false, None),
+    (Static::GoldKey, 20, false, Some(Bonus::GoldKey)),
+    (Static::SilverKey, 21, false, Some(Bonus::SilverKey)),
+    (Static::Bed, 22, true, None),
+    (Static::Basket, 23, false, None),
+    (Static::Food, 24, false, Some(Bonus::Food)),
+    (Static::FirstAid, 25, false, Some(Bonus::FirstAid)),
+    (Static::Clip, 26, false, Some(Bonus::Clip)),
+    (Static::MachineGun, 27, false, Some(Bonus::MachineGun)),
+    (Static::ChainGun, 28, false, Some(Bonus::ChainGun)),
+    (Static::Cross, 29, false, Some(Bonus::Cross)),
+    (Static::Chalice, 30, false, Some(Bonus::Chalice)),
+    (Static::Chest, 31, false, Some(Bonus::Chest)),
+    (Static::Crown, 32, false, Some(Bonus::Crown)),
+    (Static::OneUp, 33, false, Some(Bonus::FullHeal)),
+    (Static::Gibs, 34, false, Some(Bonus::Gibs)),
+    (Static::Barrel, 35, true, None),
+    (Static::Well, 36, true, None),
+    (Static::EmptyWell, 37, true, None),
+    (Static::Gibs2, 38, false, Some(Bonus::Gibs)),
+    (Static::Flag, 39, true, None),
+    (Static::CallApogee, 40, true, None),
+    (Static::Junk1, 41, false, None),
+    (Static::Junk2, 42, false, None),
+    (Static::Junk3, 43, false, None),
+    (Static::Pots, 44, false, None),
+    (Static::Stove, 45, true, None),
+    (Static::Spears, 46, true, None),
+    (Static::Vines, 47, false, None),
+    (Static::DroppedClip, 26, false, Some(Bonus::HalfClip)),
+];
+
+impl Static {
+    /// The static object of a plane 1 code, if it's one.
+    pub fn from_code(code: u16) -> Option<Static> {
+        let index = code.checked_sub(FIRST_STATIC_CODE)? as usize;
+        STATIC_INFO.get(index).map(|&(object, ..)| object)
+    }
+
+    /// The sprite number to draw it with, see `Cache::get_sprite`.
+    pub fn sprite(&self) -> usize {
+        SPR_STAT_0 + STATIC_INFO[*self as usize].1
+    }
+
+    /// Whether it stops the player and enemies from walking through its tile.
+    pub fn is_blocking(&self) -> bool {
+        STATIC_INFO[*self as usize].2
+    }
+
+    /// What the player gets by walking over it, if it can be picked up.
+    pub fn bonus(&self) -> Option<Bonus> {
+        STATIC_INFO[*self as usize].3
+    }
+}
+
+/// The planes of a level, each holding a word per tile, row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Map {
-    plane0: [[u16; MAP_HEIGHT]; MAP_WIDTH],
-    plane1: [[u16; MAP_HEIGHT]; MAP_WIDTH],
+    width: usize,
+    height: usize,
+    plane0: Vec<u16>,
+    plane1: Vec<u16>,
+    /// Unused by the game, but mods keep things like floor and ceiling codes there.
+    plane2: Option<Vec<u16>>,
     pub name: String,
 }
 
 impl Map {
+    /// Panics if the planes don't hold `width` * `height` words.
     pub fn new(
-        plane0: [[u16; MAP_HEIGHT]; MAP_WIDTH],
-        plane1: [[u16; MAP_HEIGHT]; MAP_WIDTH],
+        width: usize,
+        height: usize,
+        plane0: Vec<u16>,
+        plane1: Vec<u16>,
         name: String,
     ) -> Self {
+        assert_eq!(width * height, plane0.len(), "plane 0 size");
+        assert_eq!(width * height, plane1.len(), "plane 1 size");
         Self {
+            width,
+            height,
             plane0,
             plane1,
+            plane2: None,
             name,
         }
     }
 
-    pub fn tile_at(&self, x: u8, y: u8) -> Tile {
-        let tile = self.plane0[x as usize][y as usize];
+    /// Adds plane 2 to the map. Panics if it doesn't hold `width` * `height` words.
+    pub fn with_plane2(mut self, plane2: Vec<u16>) -> Self {
+        assert_eq!(self.width * self.height, plane2.len(), "plane 2 size");
+        self.plane2 = Some(plane2);
+        self
+    }
+
+    /// The words of a plane, row by row. Plane 2 is only there if the map has one.
+    pub fn plane(&self, n: usize) -> Option<&[u16]> {
+        match n {
+            0 => Some(&self.plane0),
+            1 => Some(&self.plane1),
+            2 => self.plane2.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// The raw word of the walls plane (plane 0) at the given position. Outside the map
+    /// this is 0, which is solid.
+    pub fn tile_code(&self, x: usize, y: usize) -> u16 {
+        self.index(x, y).map_or(0, |i| self.plane0[i])
+    }
+
+    /// Changes the walls plane word at the given position, if it's in the map.
+    pub fn set_tile_code(&mut self, x: usize, y: usize, code: u16) {
+        if let Some(i) = self.index(x, y) {
+            self.plane0[i] = code;
+        }
+    }
+
+    /// The raw word of the objects plane (plane 1) at the given position, 0 outside the map.
+    pub fn actor_code(&self, x: usize, y: usize) -> u16 {
+        self.index(x, y).map_or(0, |i| self.plane1[i])
+    }
+
+    /// Changes the objects plane word at the given position, if it's in the map.
+    pub fn set_actor_code(&mut self, x: usize, y: usize, code: u16) {
+        if let Some(i) = self.index(x, y) {
+            self.plane1[i] = code;
+        }
+    }
+
+    pub fn tile_at(&self, x: usize, y: usize) -> Tile {
+        let tile = self.tile_code(x, y);
         match tile {
-            90 | 92 | 94 | 96 | 98 | 100 => Tile::Door {
-                vertical: true,
-                lock: (tile - 90) / 2,
+            FIRST_DOOR_TILE..=LAST_DOOR_TILE => Tile::Door {
+                vertical: (tile - FIRST_DOOR_TILE) & 1 == 0,
+                lock: (tile - FIRST_DOOR_TILE) / 2,
             },
-            91 | 93 | 95 | 97 | 99 | 101 => Tile::Door {
-                vertical: false,
-                lock: (tile - 91) / 2,
+            ELEVATOR_TILE => Tile::Elevator,
+            FAKE_ELEVATOR_TILE => Tile::FakeElevator,
+            AMBUSH_TILE => Tile::AmbushFloor,
+            // keep the tile number to find the proper texture, dead ends included
+            n if n < AREA_TILE => Tile::Wall(tile),
+            // codes past the last area aren't used by the original levels
+            n => Tile::Floor {
+                area: (n - AREA_TILE).min(NUM_AREAS - 1) as u8,
             },
-            106 => Tile::Floor, // this one is actually an ambush tile, review if we need to do something with it
-            n if n < 107 => Tile::Wall(tile), // keep the tile number to find the proper texture
-            _ => Tile::Floor,
         }
     }
 
-    pub fn actor_at(&self, x: u8, y: u8) -> Option<Actor> {
-        match self.plane1[x as usize][y as usize] {
+    /// Whether using the elevator switch next to this tile leads to the secret level.
+    pub fn is_secret_exit(&self, x: usize, y: usize) -> bool {
+        self.tile_code(x, y) == ALT_ELEVATOR_TILE
+    }
+
+    pub fn actor_at(&self, x: usize, y: usize) -> Option<Actor> {
+        match self.actor_code(x, y) {
             19 => Some(Actor::Player(Direction::North)),
             20 => Some(Actor::Player(Direction::East)),
             21 => Some(Actor::Player(Direction::South)),
             22 => Some(Actor::Player(Direction::West)),
-            n if (23..=72).contains(&n) => Some(Actor::Item),
-            98 => Some(Actor::PushWall),
+            PUSH_WALL_CODE => Some(Actor::PushWall),
             124 => Some(Actor::DeadGuard),
-            n if n >= 108 => Some(Actor::Enemy),
-            _ => None,
+            n if n >= 108 => EnemySpawn::from_code(n).map(Actor::Enemy),
+            n => Static::from_code(n).map(Actor::Static),
         }
     }
 
@@ -92,38 +499,218 @@ impl Map {
             y: player_y,
             view_angle: player_angle,
             move_angle: player_angle,
+            keys: player::Keys::default(),
+        }
+    }
+
+    /// Where the static objects are, row by row.
+    pub fn statics(&self) -> Vec<(usize, usize, Static)> {
+        let mut statics = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if let Some(Actor::Static(object)) = self.actor_at(x, y) {
+                    statics.push((x, y, object));
+                }
+            }
+        }
+        statics
+    }
+
+    /// Where the enemies that show up at the given skill level start, row by row.
+    pub fn enemy_spawns(&self, skill: usize) -> Vec<(usize, usize, EnemySpawn)> {
+        let mut spawns = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if let Some(Actor::Enemy(spawn)) = self.actor_at(x, y) {
+                    if spawn.min_skill <= skill {
+                        spawns.push((x, y, spawn));
+                    }
+                }
+            }
         }
+        spawns
+    }
+
+    pub fn find_player_start(&self) -> (usize, usize, Direction) {
+        self.player_start()
+            .expect("Can't find the player in the map")
     }
 
-    pub fn find_player_start(&self) -> (u8, u8, Direction) {
-        for x in 0..MAP_WIDTH as u8 {
-            for y in 0..MAP_HEIGHT as u8 {
+    /// Position and direction of the player start, if the map has one.
+    pub fn player_start(&self) -> Option<(usize, usize, Direction)> {
+        for x in 0..self.width {
+            for y in 0..self.height {
                 if let Some(Actor::Player(direction)) = self.actor_at(x, y) {
-                    return (x, y, direction);
+                    return Some((x, y, direction));
                 }
             }
         }
-        panic!("Can't find the player in the map");
+        None
     }
 }
 
 impl fmt::Display for Map {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for x in 0..MAP_WIDTH {
-            for y in 0..MAP_HEIGHT {
-                let word = self.plane0[x][y];
-                if word == 90 {
-                    write!(f, "|").unwrap();
-                } else if word == 91 {
-                    write!(f, "-").unwrap();
-                } else if word < 107 {
-                    write!(f, "W").unwrap();
-                } else {
-                    write!(f, " ").unwrap();
-                }
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let c = match self.tile_at(x, y) {
+                    Tile::Door { vertical: true, .. } => '|',
+                    Tile::Door {
+                        vertical: false, ..
+                    } => '-',
+                    tile if tile.is_solid() => 'W',
+                    _ => ' ',
+                };
+                write!(f, "{}", c).unwrap();
             }
             writeln!(f).unwrap();
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_map(plane0: Vec<u16>) -> Map {
+        let width = plane0.len();
+        Map::new(width, 1, plane0, vec![0; width], "Row".to_string())
+    }
+
+    #[test]
+    fn plane0_codes_are_decoded() {
+        let map = row_map(vec![
+            1, 21, 22, 63, 64, 90, 91, 96, 101, 102, 106, 107, 108, 143, 200,
+        ]);
+        let tiles: Vec<Tile> = (0..map.width()).map(|x| map.tile_at(x, 0)).collect();
+        assert_eq!(
+            vec![
+                Tile::Wall(1),
+                Tile::Elevator,
+                Tile::FakeElevator,
+                Tile::Wall(63),
+                Tile::Wall(64),
+                Tile::Door {
+                    vertical: true,
+                    lock: 0
+                },
+                Tile::Door {
+                    vertical: false,
+                    lock: 0
+                },
+                Tile::Door {
+                    vertical: true,
+                    lock: 3
+                },
+                Tile::Door {
+                    vertical: false,
+                    lock: 5
+                },
+                Tile::Wall(102),
+                Tile::AmbushFloor,
+                Tile::Floor { area: 0 },
+                Tile::Floor { area: 1 },
+                Tile::Floor { area: 36 },
+                Tile::Floor { area: 36 },
+            ],
+            tiles
+        );
+        assert!(map.is_secret_exit(11, 0));
+        assert!(!map.is_secret_exit(12, 0));
+        for code in DEAD_END_TILES.iter().cloned().flatten() {
+            assert_eq!(Tile::Wall(code), row_map(vec![code]).tile_at(0, 0));
+        }
+        // outside the map is solid
+        assert!(map.tile_at(15, 0).is_solid());
+    }
+
+    #[test]
+    fn statics_have_sprites_and_bonuses() {
+        let plane1 = vec![23, 24, 43, 56, 71, 72];
+        let map = Map::new(6, 1, vec![108; 6], plane1, "Row".to_string());
+        let statics: Vec<Option<Actor>> = (0..6).map(|x| map.actor_at(x, 0)).collect();
+        assert_eq!(
+            vec![
+                Some(Actor::Static(Static::Puddle)),
+                Some(Actor::Static(Static::GreenBarrel)),
+                Some(Actor::Static(Static::GoldKey)),
+                Some(Actor::Static(Static::OneUp)),
+                Some(Actor::Static(Static::DroppedClip)),
+                None,
+            ],
+            statics
+        );
+
+        assert_eq!((2, false, None), info(Static::Puddle));
+        assert_eq!((3, true, None), info(Static::GreenBarrel));
+        assert_eq!((22, false, Some(Bonus::GoldKey)), info(Static::GoldKey));
+        assert_eq!((49, false, None), info(Static::Vines));
+        assert_eq!(info(Static::Clip).0, info(Static::DroppedClip).0);
+        assert_eq!(Some(Bonus::HalfClip), Static::DroppedClip.bonus());
+    }
+
+    #[test]
+    fn enemies_have_kind_direction_and_skill() {
+        let spawn = |code| EnemySpawn::from_code(code).unwrap();
+        let guard = spawn(108);
+        assert_eq!(
+            (Enemy::Guard, Some(Direction::East), false, 0),
+            (
+                guard.kind,
+                guard.direction,
+                guard.patrolling,
+                guard.min_skill
+            )
+        );
+        let officer = spawn(120 + 36 + 1);
+        assert_eq!(
+            (Enemy::Officer, Some(Direction::North), true, 2),
+            (
+                officer.kind,
+                officer.direction,
+                officer.patrolling,
+                officer.min_skill
+            )
+        );
+        let dog = spawn(141 + 72);
+        assert_eq!(
+            (Enemy::Dog, Some(Direction::South), true, 3),
+            (dog.kind, dog.direction, dog.patrolling, dog.min_skill)
+        );
+        assert_eq!(Enemy::Ss, spawn(126 + 2).kind);
+        assert_eq!((Enemy::Mutant, 2), (spawn(234).kind, spawn(234).min_skill));
+        assert_eq!((Enemy::Mutant, 3), (spawn(259).kind, spawn(259).min_skill));
+        assert_eq!(
+            EnemySpawn {
+                kind: Enemy::Hans,
+                direction: None,
+                patrolling: false,
+                min_skill: 0
+            },
+            spawn(214)
+        );
+        assert_eq!(Enemy::FakeHitler, spawn(160).kind);
+        assert_eq!(Enemy::Inky, spawn(227).kind);
+        // dead guard, and codes no enemy uses
+        for code in [124, 125, 142, 161, 260] {
+            assert_eq!(None, EnemySpawn::from_code(code));
+        }
+    }
+
+    #[test]
+    fn enemy_spawns_follow_skill() {
+        let plane1 = vec![108, 144, 180, 124];
+        let map = Map::new(4, 1, vec![108; 4], plane1, "Row".to_string());
+        let at_skill =
+            |skill| -> Vec<usize> { map.enemy_spawns(skill).iter().map(|&(x, ..)| x).collect() };
+        assert_eq!(vec![0], at_skill(0));
+        assert_eq!(vec![0], at_skill(1));
+        assert_eq!(vec![0, 1], at_skill(2));
+        assert_eq!(vec![0, 1, 2], at_skill(3));
+    }
+
+    fn info(object: Static) -> (usize, bool, Option<Bonus>) {
+        (object.sprite(), object.is_blocking(), object.bonus())
+    }
+}
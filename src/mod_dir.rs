@@ -0,0 +1,370 @@
+// Loose files that replace assets of the data set, to try texture packs and level mods
+// without rebuilding the data files. They are named the way `extract` and `export-maps`
+// write them: `textures/012.png`, `sprites/209.png`, `pics/TITLEPIC.png` and
+// `maps/E1M1.json` (in the plain JSON format described in `export`). Images are
+// quantised to the game palette as they're loaded.
+
+use crate::cache::{CacheError, Picture, Span, Sprite};
+use crate::constants::{SPRITE_SIZE, WALLPIC_HEIGHT, WALLPIC_WIDTH};
+use crate::export::MapDocument;
+use crate::map::Map;
+use crate::ColorMap;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Pixels less opaque than this are transparent in sprites.
+const ALPHA_THRESHOLD: u8 = 128;
+
+pub struct ModDir {
+    dir: PathBuf,
+    color_map: ColorMap,
+}
+
+/// An image quantised to the palette, row by row, `None` being the transparent pixels.
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Option<u8>>,
+}
+
+impl ModDir {
+    pub fn new(dir: &Path, color_map: ColorMap) -> Self {
+        ModDir {
+            dir: dir.to_path_buf(),
+            color_map,
+        }
+    }
+
+    /// A replacement wall texture, stored column by column like the VSWAP ones.
+    pub fn texture(&self, index: usize) -> Result<Option<Vec<u8>>, CacheError> {
+        let path = self.dir.join(format!("textures/{:03}.png", index));
+        let image = match self.image(&path)? {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+        check_size(&path, &image, WALLPIC_WIDTH, WALLPIC_HEIGHT)?;
+
+        let mut texture = Vec::with_capacity(WALLPIC_WIDTH * WALLPIC_HEIGHT);
+        for x in 0..WALLPIC_WIDTH {
+            for y in 0..WALLPIC_HEIGHT {
+                texture.push(image.pixels[y * WALLPIC_WIDTH + x].unwrap_or(0));
+            }
+        }
+        Ok(Some(texture))
+    }
+
+    pub fn sprite(&self, index: usize) -> Result<Option<Sprite>, CacheError> {
+        let path = self.dir.join(format!("sprites/{:03}.png", index));
+        let image = match self.image(&path)? {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+        check_size(&path, &image, SPRITE_SIZE, SPRITE_SIZE)?;
+        Ok(Some(sprite_from_pixels(&image.pixels)))
+    }
+
+    /// A replacement picture, e.g. "TITLEPIC" or "147" for the ones without a name.
+    /// Pictures are stored as 4 planes, so their width must be a multiple of 4.
+    pub fn pic(&self, name: &str) -> Result<Option<Picture>, CacheError> {
+        let path = self.dir.join(format!("pics/{}.png", name));
+        let image = match self.image(&path)? {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+        if image.width % 4 != 0 {
+            return Err(bad_file(
+                &path,
+                "the width of a picture must be a multiple of 4",
+            ));
+        }
+
+        let (width, height) = (image.width, image.height);
+        let plane_width = width / 4;
+        let mut data = vec![0; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let target = y * plane_width + x / 4 + (x % 4) * plane_width * height;
+                data[target] = image.pixels[y * width + x].unwrap_or(0);
+            }
+        }
+        Ok(Some(Picture {
+            width: width as u32,
+            height: height as u32,
+            data,
+        }))
+    }
+
+    pub fn map(&self, episode: usize, level: usize) -> Result<Option<Map>, CacheError> {
+        let path = self
+            .dir
+            .join(format!("maps/E{}M{}.json", episode + 1, level + 1));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let json = fs::read_to_string(&path).map_err(|source| CacheError::MissingFile {
+            path: path.clone(),
+            source,
+        })?;
+        let document: MapDocument =
+            serde_json::from_str(&json).map_err(|err| bad_file(&path, &err.to_string()))?;
+        let map = document
+            .to_map()
+            .ok_or_else(|| bad_file(&path, "the planes don't match the width and height"))?;
+        if map.player_start().is_none() {
+            return Err(bad_file(&path, "the map has no player start"));
+        }
+        Ok(Some(map))
+    }
+
+    /// Reads and quantises a PNG file, `None` if there's no such file.
+    fn image(&self, path: &Path) -> Result<Option<Image>, CacheError> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let file = File::open(path).map_err(|source| CacheError::MissingFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let decode_error = |err: png::DecodingError| bad_file(path, &err.to_string());
+
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(decode_error)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(decode_error)?;
+        let channels = info.color_type.samples();
+        let rgba = |pixel: &[u8]| match info.color_type {
+            png::ColorType::Grayscale => (pixel[0], pixel[0], pixel[0], 0xFF),
+            png::ColorType::GrayscaleAlpha => (pixel[0], pixel[0], pixel[0], pixel[1]),
+            png::ColorType::Rgb => (pixel[0], pixel[1], pixel[2], 0xFF),
+            _ => (pixel[0], pixel[1], pixel[2], pixel[3]),
+        };
+
+        let (width, height) = (info.width as usize, info.height as usize);
+        let mut nearest = HashMap::new();
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in buffer[..info.buffer_size()].chunks_exact(info.line_size) {
+            for pixel in row.chunks_exact(channels).take(width) {
+                let (r, g, b, a) = rgba(pixel);
+                pixels.push(if a < ALPHA_THRESHOLD {
+                    None
+                } else {
+                    Some(
+                        *nearest
+                            .entry((r, g, b))
+                            .or_insert_with(|| nearest_color(&self.color_map, (r, g, b))),
+                    )
+                });
+            }
+        }
+        Ok(Some(Image {
+            width,
+            height,
+            pixels,
+        }))
+    }
+}
+
+fn bad_file(path: &Path, reason: &str) -> CacheError {
+    CacheError::BadModFile {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    }
+}
+
+fn check_size(path: &Path, image: &Image, width: usize, height: usize) -> Result<(), CacheError> {
+    if image.width != width || image.height != height {
+        return Err(bad_file(
+            path,
+            &format!(
+                "image is {}x{}, it should be {}x{}",
+                image.width, image.height, width, height
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The palette index closest to a color, the first one if there's a tie.
+fn nearest_color(color_map: &ColorMap, (r, g, b): (u8, u8, u8)) -> u8 {
+    let distance = |&(pr, pg, pb): &(u8, u8, u8)| {
+        let (dr, dg, db) = (
+            pr as i32 - r as i32,
+            pg as i32 - g as i32,
+            pb as i32 - b as i32,
+        );
+        dr * dr + dg * dg + db * db
+    };
+    color_map
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| distance(color))
+        .map_or(0, |(index, _)| index as u8)
+}
+
+/// Turns a 64x64 grid into the columns of spans of a sprite, from its first column with
+/// something to draw to its last one.
+fn sprite_from_pixels(pixels: &[Option<u8>]) -> Sprite {
+    let column_spans = |x: usize| {
+        let mut spans: Vec<Span> = Vec::new();
+        for y in 0..SPRITE_SIZE {
+            let color = match pixels[y * SPRITE_SIZE + x] {
+                Some(color) => color,
+                None => continue,
+            };
+            match spans.last_mut() {
+                Some(span) if span.start as usize + span.pixels.len() == y => {
+                    span.pixels.push(color)
+                }
+                _ => spans.push(Span {
+                    start: y as u16,
+                    pixels: vec![color],
+                }),
+            }
+        }
+        spans
+    };
+    let columns: Vec<Vec<Span>> = (0..SPRITE_SIZE).map(column_spans).collect();
+
+    let left = columns.iter().position(|spans| !spans.is_empty());
+    let right = columns.iter().rposition(|spans| !spans.is_empty());
+    let (left, right) = match (left, right) {
+        (Some(left), Some(right)) => (left, right),
+        // nothing to draw, keep a single empty column
+        _ => (0, 0),
+    };
+    Sprite {
+        left_pix: left as u16,
+        right_pix: right as u16,
+        columns: columns[left..=right].to_vec(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::export;
+
+    /// A palette where every color is different.
+    pub(crate) fn test_color_map() -> ColorMap {
+        let mut color_map = [(0, 0, 0); 256];
+        for (i, color) in color_map.iter_mut().enumerate() {
+            *color = (i as u8, 0, 255 - i as u8);
+        }
+        color_map
+    }
+
+    pub(crate) fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut encoder = png::Encoder::new(File::create(path).unwrap(), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(rgba).unwrap();
+    }
+
+    fn mod_dir(name: &str) -> (PathBuf, ModDir) {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        (dir.clone(), ModDir::new(&dir, test_color_map()))
+    }
+
+    #[test]
+    fn textures_are_quantised_and_transposed() {
+        let (dir, mod_dir) = mod_dir("rustenstein_mod_texture_test");
+        // color 10 everywhere, but something close to color 200 on the first row
+        let mut rgba: Vec<u8> = [10, 0, 245, 255].repeat(64 * 64);
+        rgba[4..8].copy_from_slice(&[200, 3, 55, 255]);
+        write_png(&dir.join("textures/012.png"), 64, 64, &rgba);
+
+        let texture = mod_dir.texture(12).unwrap().unwrap();
+        assert_eq!(10, texture[0]);
+        assert_eq!(200, texture[64]);
+        assert_eq!(10, texture[1]);
+        assert!(mod_dir.texture(13).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn textures_must_be_64x64() {
+        let (dir, mod_dir) = mod_dir("rustenstein_mod_texture_size_test");
+        write_png(&dir.join("textures/000.png"), 2, 1, &[0; 8]);
+        assert!(matches!(
+            mod_dir.texture(0),
+            Err(CacheError::BadModFile { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sprites_are_made_of_the_opaque_pixels() {
+        let (dir, mod_dir) = mod_dir("rustenstein_mod_sprite_test");
+        let mut rgba = vec![0; 64 * 64 * 4];
+        for y in [3, 4, 10] {
+            let i = (y * 64 + 2) * 4;
+            rgba[i..i + 4].copy_from_slice(&[5, 0, 250, 255]);
+        }
+        write_png(&dir.join("sprites/209.png"), 64, 64, &rgba);
+
+        let sprite = mod_dir.sprite(209).unwrap().unwrap();
+        assert_eq!((2, 2), (sprite.left_pix, sprite.right_pix));
+        assert_eq!(
+            vec![vec![
+                Span {
+                    start: 3,
+                    pixels: vec![5, 5]
+                },
+                Span {
+                    start: 10,
+                    pixels: vec![5]
+                },
+            ]],
+            sprite.columns
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pics_are_planar() {
+        let (dir, mod_dir) = mod_dir("rustenstein_mod_pic_test");
+        let rgba: Vec<u8> = (0..8 * 2)
+            .flat_map(|i| [i as u8, 0, 255 - i as u8, 255])
+            .collect();
+        write_png(&dir.join("pics/TITLEPIC.png"), 8, 2, &rgba);
+
+        let pic = mod_dir.pic("TITLEPIC").unwrap().unwrap();
+        assert_eq!((8, 2), (pic.width, pic.height));
+        assert_eq!(
+            vec![0, 4, 8, 12, 1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15],
+            pic.data
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn maps_are_read_from_json() {
+        let (dir, mod_dir) = mod_dir("rustenstein_mod_map_test");
+        let map = Map::new(2, 1, vec![1, 108], vec![0, 19], "Mod".to_string());
+        fs::create_dir_all(dir.join("maps")).unwrap();
+        fs::write(dir.join("maps/E1M2.json"), export::to_json(&map)).unwrap();
+
+        assert_eq!(Some(map), mod_dir.map(0, 1).unwrap());
+        assert!(mod_dir.map(0, 0).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn maps_need_a_player_start() {
+        let (dir, mod_dir) = mod_dir("rustenstein_mod_map_player_test");
+        let map = Map::new(2, 1, vec![1, 108], vec![0, 0], "Mod".to_string());
+        fs::create_dir_all(dir.join("maps")).unwrap();
+        fs::write(dir.join("maps/E1M1.json"), export::to_json(&map)).unwrap();
+
+        assert!(matches!(
+            mod_dir.map(0, 0),
+            Err(CacheError::BadModFile { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,748 @@
+// OPL2 (YM3812) FM synthesis, enough of it to play the AdLib sound effects and the IMF
+// music of the game. Rhythm mode and the timers are not emulated, the game doesn't use them.
+// On how the chip works: https://moddingwiki.shikadi.net/wiki/OPL_chip
+// The log-sin and exponent tables, as well as the envelope and LFO timings follow what was
+// found by decapping the chip, see https://github.com/nukeykt/Nuked-OPL3
+
+use crate::audio::Sound;
+use crate::cache::{AdlibSound, ImfSong, Instrument};
+use std::f64::consts::PI;
+
+/// The chip produces one sample every 72 cycles of its 3.58MHz clock.
+pub const OPL_RATE: u32 = 49716;
+/// IMF music is played at 700 ticks per second.
+pub const IMF_TICK_RATE: u32 = 700;
+/// AdLib sound effects play one frequency byte per tick of the 140Hz sound timer.
+pub const SFX_TICK_RATE: u32 = 140;
+
+const NUM_CHANNELS: usize = 9;
+const MAX_ATTENUATION: i32 = 0x1FF;
+
+/// Frequency multipliers of the MULT register, times two.
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// Key scale level attenuation for the top 4 bits of the frequency number.
+const KSL_TABLE: [i32; 16] = [
+    0, 32, 40, 45, 48, 51, 53, 55, 56, 58, 59, 60, 61, 62, 63, 64,
+];
+/// How much of the key scale level attenuation applies for each KSL register value.
+const KSL_SHIFT: [u32; 4] = [8, 1, 2, 0];
+
+/// Envelope increments for each rate, 4 bits per step of an 8 step cycle.
+const ENV_INCREMENTS: [u32; 64] = [
+    0x00000000, 0x00000000, 0x10101010, 0x10101010, // 0-3
+    0x10101010, 0x10101010, 0x11101110, 0x11101110, // 4-7
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 8-11
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 12-15
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 16-19
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 20-23
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 24-27
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 28-31
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 32-35
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 36-39
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 40-43
+    0x10101010, 0x10111010, 0x11101110, 0x11111110, // 44-47
+    0x11111111, 0x21112111, 0x21212121, 0x22212221, // 48-51
+    0x22222222, 0x42224222, 0x42424242, 0x44424442, // 52-55
+    0x44444444, 0x84448444, 0x84848484, 0x88848884, // 56-59
+    0x88888888, 0x88888888, 0x88888888, 0x88888888, // 60-63
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnvState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Operator {
+    // 0x20: tremolo, vibrato, sustained envelope, key scale rate and frequency multiplier
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u8,
+    // 0x40: key scale level and total level
+    key_scale_level: u8,
+    total_level: u8,
+    // 0x60 and 0x80: envelope rates and sustain level
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u8,
+    release_rate: u8,
+    // 0xE0
+    waveform: u8,
+
+    phase: u32,
+    env_state: EnvState,
+    env_level: i32,
+    out: i32,
+    prev_out: i32,
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Operator {
+            tremolo: false,
+            vibrato: false,
+            sustained: false,
+            key_scale_rate: false,
+            multiplier: 0,
+            key_scale_level: 0,
+            total_level: 0,
+            attack_rate: 0,
+            decay_rate: 0,
+            sustain_level: 0,
+            release_rate: 0,
+            waveform: 0,
+            phase: 0,
+            env_state: EnvState::Release,
+            env_level: MAX_ATTENUATION,
+            out: 0,
+            prev_out: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key_on: bool,
+    feedback: u8,
+    additive: bool,
+}
+
+impl Channel {
+    /// The key code used for key scale rate: block and the top bit(s) of the frequency number.
+    fn key_code(&self, note_select: bool) -> u8 {
+        let bit = if note_select {
+            (self.fnum >> 8) & 1
+        } else {
+            (self.fnum >> 9) & 1
+        };
+        (self.block << 1) | bit as u8
+    }
+}
+
+/// An emulated OPL2 chip. Registers are written with `write` and every call to `sample`
+/// runs the chip for one sample at `OPL_RATE`.
+pub struct Opl {
+    operators: [Operator; NUM_CHANNELS * 2],
+    channels: [Channel; NUM_CHANNELS],
+    waveform_select: bool,
+    note_select: bool,
+    deep_tremolo: bool,
+    deep_vibrato: bool,
+    timer: u32,
+    env_timer: u32,
+    tremolo_pos: u32,
+    vibrato_pos: u32,
+    log_sin: [u16; 256],
+    exp: [u16; 256],
+}
+
+impl Default for Opl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Opl {
+    pub fn new() -> Self {
+        Opl {
+            operators: [Operator::default(); NUM_CHANNELS * 2],
+            channels: [Channel::default(); NUM_CHANNELS],
+            waveform_select: false,
+            note_select: false,
+            deep_tremolo: false,
+            deep_vibrato: false,
+            timer: 0,
+            env_timer: 0,
+            tremolo_pos: 0,
+            vibrato_pos: 0,
+            log_sin: log_sin_table(),
+            exp: exp_table(),
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x01 => self.waveform_select = value & 0x20 != 0,
+            0x08 => self.note_select = value & 0x40 != 0,
+            0x20..=0x35 => {
+                if let Some(op) = self.operator_at(register - 0x20) {
+                    op.tremolo = value & 0x80 != 0;
+                    op.vibrato = value & 0x40 != 0;
+                    op.sustained = value & 0x20 != 0;
+                    op.key_scale_rate = value & 0x10 != 0;
+                    op.multiplier = value & 0x0F;
+                }
+            }
+            0x40..=0x55 => {
+                if let Some(op) = self.operator_at(register - 0x40) {
+                    op.key_scale_level = value >> 6;
+                    op.total_level = value & 0x3F;
+                }
+            }
+            0x60..=0x75 => {
+                if let Some(op) = self.operator_at(register - 0x60) {
+                    op.attack_rate = value >> 4;
+                    op.decay_rate = value & 0x0F;
+                }
+            }
+            0x80..=0x95 => {
+                if let Some(op) = self.operator_at(register - 0x80) {
+                    op.sustain_level = value >> 4;
+                    op.release_rate = value & 0x0F;
+                }
+            }
+            0xA0..=0xA8 => {
+                let channel = &mut self.channels[(register - 0xA0) as usize];
+                channel.fnum = (channel.fnum & 0x300) | value as u16;
+            }
+            0xB0..=0xB8 => {
+                let index = (register - 0xB0) as usize;
+                let channel = &mut self.channels[index];
+                channel.fnum = (channel.fnum & 0xFF) | ((value as u16 & 0x03) << 8);
+                channel.block = (value >> 2) & 0x07;
+                let key_on = value & 0x20 != 0;
+                let was_on = channel.key_on;
+                channel.key_on = key_on;
+                if key_on && !was_on {
+                    self.key_on(index);
+                } else if !key_on && was_on {
+                    for op in Self::channel_operators(index) {
+                        self.operators[op].env_state = EnvState::Release;
+                    }
+                }
+            }
+            0xBD => {
+                self.deep_tremolo = value & 0x80 != 0;
+                self.deep_vibrato = value & 0x40 != 0;
+            }
+            0xC0..=0xC8 => {
+                let channel = &mut self.channels[(register - 0xC0) as usize];
+                channel.feedback = (value >> 1) & 0x07;
+                channel.additive = value & 0x01 != 0;
+            }
+            0xE0..=0xF5 => {
+                if let Some(op) = self.operator_at(register - 0xE0) {
+                    op.waveform = value & 0x03;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs the chip for one sample and returns the mix of all its channels.
+    pub fn sample(&mut self) -> i16 {
+        self.update_lfos();
+        // the envelope generator runs at half the sample rate
+        let clock_envelopes = self.timer & 1 == 1;
+        if clock_envelopes {
+            self.env_timer = self.env_timer.wrapping_add(1);
+        }
+
+        let mut output = 0;
+        for index in 0..NUM_CHANNELS {
+            let channel = self.channels[index];
+            let [modulator, carrier] = Self::channel_operators(index);
+
+            let feedback = if channel.feedback > 0 {
+                let op = &self.operators[modulator];
+                (op.prev_out + op.out) >> (9 - channel.feedback)
+            } else {
+                0
+            };
+            let mod_out = self.run_operator(modulator, channel, feedback, clock_envelopes);
+            let car_mod = if channel.additive { 0 } else { mod_out };
+            let car_out = self.run_operator(carrier, channel, car_mod, clock_envelopes);
+
+            output += car_out;
+            if channel.additive {
+                output += mod_out;
+            }
+        }
+
+        self.timer = self.timer.wrapping_add(1);
+        output.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+
+    /// Runs the chip for `count` samples.
+    pub fn generate(&mut self, count: usize) -> Vec<i16> {
+        (0..count).map(|_| self.sample()).collect()
+    }
+
+    /// Sets up `channel` with an AdLib instrument, the way the sound effects do.
+    pub fn set_instrument(&mut self, channel: usize, instrument: &Instrument) {
+        let [modulator, carrier] = Self::channel_operators(channel);
+        for (op, chr, scale, attack, sus, wave) in [
+            (
+                modulator,
+                instrument.m_char,
+                instrument.m_scale,
+                instrument.m_attack,
+                instrument.m_sus,
+                instrument.m_wave,
+            ),
+            (
+                carrier,
+                instrument.c_char,
+                instrument.c_scale,
+                instrument.c_attack,
+                instrument.c_sus,
+                instrument.c_wave,
+            ),
+        ] {
+            let offset = Self::operator_offset(op);
+            self.write(0x20 + offset, chr);
+            self.write(0x40 + offset, scale);
+            self.write(0x60 + offset, attack);
+            self.write(0x80 + offset, sus);
+            self.write(0xE0 + offset, wave);
+        }
+        // like the original sound manager, the feedback/connection byte is not used
+        self.write(0xC0 + channel as u8, 0);
+    }
+
+    fn operator_at(&mut self, offset: u8) -> Option<&mut Operator> {
+        let group = offset / 8;
+        let slot = offset % 8;
+        if group < 3 && slot < 6 {
+            Some(&mut self.operators[(group * 6 + slot) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Register offset of the operator with the given index.
+    fn operator_offset(op: usize) -> u8 {
+        ((op / 6) * 8 + op % 6) as u8
+    }
+
+    /// Indexes of the modulator and carrier operators of a channel.
+    fn channel_operators(channel: usize) -> [usize; 2] {
+        let modulator = (channel / 3) * 6 + channel % 3;
+        [modulator, modulator + 3]
+    }
+
+    fn key_on(&mut self, channel: usize) {
+        let key_code = self.channels[channel].key_code(self.note_select);
+        for index in Self::channel_operators(channel) {
+            let op = &mut self.operators[index];
+            op.phase = 0;
+            op.env_state = EnvState::Attack;
+            if env_rate(op.attack_rate, op.key_scale_rate, key_code) >= 62 {
+                op.env_level = 0;
+                op.env_state = EnvState::Decay;
+            }
+        }
+    }
+
+    fn update_lfos(&mut self) {
+        if self.timer & 0x3F == 0x3F {
+            self.tremolo_pos = (self.tremolo_pos + 1) % 210;
+        }
+        if self.timer & 0x3FF == 0x3FF {
+            self.vibrato_pos = (self.vibrato_pos + 1) & 7;
+        }
+    }
+
+    fn tremolo(&self) -> i32 {
+        let shift = if self.deep_tremolo { 2 } else { 4 };
+        let pos = if self.tremolo_pos < 105 {
+            self.tremolo_pos
+        } else {
+            210 - self.tremolo_pos
+        };
+        (pos >> shift) as i32
+    }
+
+    /// Frequency number with vibrato applied.
+    fn vibrato_fnum(&self, fnum: u16) -> u16 {
+        let mut range = (fnum >> 7) & 7;
+        if self.vibrato_pos & 3 == 0 {
+            range = 0;
+        } else if self.vibrato_pos & 1 == 1 {
+            range >>= 1;
+        }
+        if !self.deep_vibrato {
+            range >>= 1;
+        }
+        if self.vibrato_pos & 4 != 0 {
+            fnum.wrapping_sub(range) & 0x3FF
+        } else {
+            fnum.wrapping_add(range) & 0x3FF
+        }
+    }
+
+    /// Computes the output of an operator for the current sample, with its phase shifted
+    /// by `modulation`, then advances its phase and envelope.
+    fn run_operator(
+        &mut self,
+        index: usize,
+        channel: Channel,
+        modulation: i32,
+        clock_envelopes: bool,
+    ) -> i32 {
+        let op = self.operators[index];
+
+        // attenuation in 0.1875dB units
+        let ksl =
+            (KSL_TABLE[(channel.fnum >> 6) as usize] << 2) - ((8 - channel.block as i32) << 5);
+        let ksl = ksl.max(0) >> KSL_SHIFT[op.key_scale_level as usize];
+        let tremolo = if op.tremolo { self.tremolo() } else { 0 };
+        let attenuation =
+            (op.env_level + ((op.total_level as i32) << 2) + ksl + tremolo).min(MAX_ATTENUATION);
+
+        let waveform = if self.waveform_select { op.waveform } else { 0 };
+        let phase = ((op.phase >> 9) as i32 + modulation) as u32 & 0x3FF;
+        let out = self.wave(waveform, phase, attenuation);
+
+        let fnum = if op.vibrato {
+            self.vibrato_fnum(channel.fnum)
+        } else {
+            channel.fnum
+        };
+        let base = ((fnum as u32) << channel.block) >> 1;
+        let increment = (base * MULTIPLIERS[op.multiplier as usize]) >> 1;
+
+        let key_code = channel.key_code(self.note_select);
+        let env_timer = self.env_timer;
+        let op = &mut self.operators[index];
+        op.phase = (op.phase + increment) & 0x7FFFF;
+        op.prev_out = op.out;
+        op.out = out;
+        if clock_envelopes {
+            op.step_envelope(env_timer, key_code);
+        }
+        out
+    }
+
+    /// Looks up the sample of a waveform at a 10 bit phase, attenuated by `attenuation`.
+    fn wave(&self, waveform: u8, phase: u32, attenuation: i32) -> i32 {
+        let quarter = (phase & 0xFF) as usize;
+        let log_sin = |mirrored: bool| {
+            if mirrored {
+                self.log_sin[quarter ^ 0xFF] as i32
+            } else {
+                self.log_sin[quarter] as i32
+            }
+        };
+        let second_quarter = phase & 0x100 != 0;
+        let second_half = phase & 0x200 != 0;
+
+        // 0x1000 is loud enough of an attenuation to be silent
+        let (level, negative) = match waveform {
+            0 => (log_sin(second_quarter), second_half),
+            1 if second_half => (0x1000, false),
+            1 | 2 => (log_sin(second_quarter), false),
+            _ if second_quarter => (0x1000, false),
+            _ => (log_sin(false), false),
+        };
+
+        let out = self.attenuate(level + (attenuation << 3));
+        if negative {
+            !out
+        } else {
+            out
+        }
+    }
+
+    fn attenuate(&self, level: i32) -> i32 {
+        let level = level.min(0x1FFF);
+        ((self.exp[(level & 0xFF) as usize] as i32) << 1) >> (level >> 8)
+    }
+}
+
+impl Operator {
+    fn step_envelope(&mut self, env_timer: u32, key_code: u8) {
+        let reg_rate = match self.env_state {
+            EnvState::Attack => self.attack_rate,
+            EnvState::Decay => self.decay_rate,
+            EnvState::Sustain if self.sustained => return,
+            EnvState::Sustain | EnvState::Release => self.release_rate,
+        };
+        let rate = env_rate(reg_rate, self.key_scale_rate, key_code);
+        if rate == 0 {
+            return;
+        }
+
+        let rate_shift = rate >> 2;
+        let counter = env_timer << rate_shift;
+        if counter & 0x7FF != 0 {
+            return;
+        }
+        let step = (counter >> rate_shift.max(11)) & 7;
+        let increment = ((ENV_INCREMENTS[rate as usize] >> (4 * step)) & 0x0F) as i32;
+
+        match self.env_state {
+            EnvState::Attack => {
+                if rate < 62 {
+                    self.env_level += (!self.env_level * increment) >> 3;
+                }
+                if self.env_level <= 0 {
+                    self.env_level = 0;
+                    self.env_state = EnvState::Decay;
+                }
+            }
+            EnvState::Decay => {
+                self.env_level = (self.env_level + increment).min(MAX_ATTENUATION);
+                if self.env_level >= self.sustain_attenuation() {
+                    self.env_state = EnvState::Sustain;
+                }
+            }
+            EnvState::Sustain | EnvState::Release => {
+                self.env_level = (self.env_level + increment).min(MAX_ATTENUATION);
+            }
+        }
+    }
+
+    /// Sustain level in attenuation units, 3dB per step and 93dB for the last one.
+    fn sustain_attenuation(&self) -> i32 {
+        if self.sustain_level == 15 {
+            0x1F0
+        } else {
+            (self.sustain_level as i32) << 4
+        }
+    }
+}
+
+/// The 6 bit envelope rate for a 4 bit register value, scaled up for higher notes.
+fn env_rate(reg_rate: u8, key_scale_rate: bool, key_code: u8) -> u32 {
+    if reg_rate == 0 {
+        return 0;
+    }
+    let scaling = if key_scale_rate {
+        key_code
+    } else {
+        key_code >> 2
+    };
+    (reg_rate as u32 * 4 + scaling as u32).min(63)
+}
+
+/// -log2(sin(x)) for the first quarter of a sine wave, in 1/256 units.
+fn log_sin_table() -> [u16; 256] {
+    let mut table = [0; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let x = ((i as f64 + 0.5) * PI / 512.0).sin();
+        *entry = (-x.log2() * 256.0).round() as u16;
+    }
+    table
+}
+
+/// 2^x for the fractional part of the attenuation, as 10 bit mantissas.
+fn exp_table() -> [u16; 256] {
+    let mut table = [0; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = (2f64.powf((255 - i) as f64 / 256.0) * 1024.0).round() as u16;
+    }
+    table
+}
+
+/// Renders an IMF song, running the chip for as long as the song lasts.
+pub fn render_imf(song: &ImfSong) -> Sound {
+    let mut opl = Opl::new();
+    opl.write(0x01, 0x20);
+
+    let mut samples = Vec::new();
+    let mut ticks: u64 = 0;
+    for command in &song.commands {
+        opl.write(command.register, command.value);
+        ticks += command.delay as u64;
+        let end = (ticks * OPL_RATE as u64 / IMF_TICK_RATE as u64) as usize;
+        while samples.len() < end {
+            samples.push(opl.sample());
+        }
+    }
+
+    Sound {
+        rate: OPL_RATE,
+        samples,
+    }
+}
+
+/// Renders an AdLib sound effect the way the original sound manager plays it: on the
+/// first channel, changing the note at every tick of the sound timer.
+pub fn render_adlib_sound(sound: &AdlibSound) -> Sound {
+    const FREQ_LOW: u8 = 0xA0;
+    const FREQ_HIGH: u8 = 0xB0;
+
+    let mut opl = Opl::new();
+    opl.write(0x01, 0x20);
+    opl.write(FREQ_HIGH, 0);
+    opl.set_instrument(0, &sound.instrument);
+    let block = ((sound.block & 7) << 2) | 0x20;
+
+    let mut samples = Vec::new();
+    for (tick, &note) in sound.data.iter().enumerate() {
+        if note == 0 {
+            opl.write(FREQ_HIGH, 0);
+        } else {
+            opl.write(FREQ_LOW, note);
+            opl.write(FREQ_HIGH, block);
+        }
+        let end = (tick as u64 + 1) * OPL_RATE as u64 / SFX_TICK_RATE as u64;
+        while (samples.len() as u64) < end {
+            samples.push(opl.sample());
+        }
+    }
+
+    Sound {
+        rate: OPL_RATE,
+        samples,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ImfCommand;
+
+    /// A plain sine: instant attack, no decay, carrier at full volume.
+    const SINE: Instrument = Instrument {
+        m_char: 0x21,
+        c_char: 0x21,
+        m_scale: 0x3F,
+        c_scale: 0x00,
+        m_attack: 0xF0,
+        c_attack: 0xF0,
+        m_sus: 0x00,
+        c_sus: 0x00,
+        m_wave: 0,
+        c_wave: 0,
+        n_conn: 0,
+        voice: 0,
+        mode: 0,
+    };
+
+    fn imf(register: u8, value: u8, delay: u16) -> ImfCommand {
+        ImfCommand {
+            register,
+            value,
+            delay,
+        }
+    }
+
+    #[test]
+    fn tables_match_the_chip_rom() {
+        let log_sin = log_sin_table();
+        assert_eq!(2137, log_sin[0]);
+        assert_eq!(0, log_sin[255]);
+        let exp = exp_table();
+        assert_eq!(2042, exp[0]);
+        assert_eq!(1024, exp[255]);
+    }
+
+    #[test]
+    fn silent_without_key_on() {
+        let mut opl = Opl::new();
+        opl.set_instrument(0, &SINE);
+        assert!(opl.generate(1000).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn sine_has_the_programmed_frequency() {
+        let mut opl = Opl::new();
+        opl.set_instrument(0, &SINE);
+        // fnum 577 in block 4 is 437.7Hz
+        opl.write(0xA0, 0x41);
+        opl.write(0xB0, 0x20 | (4 << 2) | 0x02);
+        let samples = opl.generate(OPL_RATE as usize);
+
+        let crossings = samples
+            .windows(2)
+            .filter(|w| (w[0] < 0) != (w[1] < 0))
+            .count();
+        assert!((874..=877).contains(&crossings), "{}", crossings);
+        assert_eq!(4084, *samples.iter().max().unwrap());
+    }
+
+    #[test]
+    fn key_off_releases_the_note() {
+        let mut opl = Opl::new();
+        opl.set_instrument(
+            0,
+            &Instrument {
+                c_sus: 0x0F,
+                ..SINE
+            },
+        );
+        opl.write(0xA0, 0x41);
+        opl.write(0xB0, 0x32);
+        opl.generate(1000);
+        opl.write(0xB0, 0x12);
+        let samples = opl.generate(4000);
+        assert!(samples[..100].iter().any(|&s| s.abs() > 1000));
+        assert!(samples[3000..].iter().all(|&s| s.abs() <= 1));
+    }
+
+    #[test]
+    fn waveform_select_needs_to_be_enabled() {
+        let half_sine = Instrument { c_wave: 1, ..SINE };
+        let mut opl = Opl::new();
+        opl.set_instrument(0, &half_sine);
+        opl.write(0xA0, 0x41);
+        opl.write(0xB0, 0x32);
+        assert!(opl.generate(200).iter().any(|&s| s < -1));
+
+        let mut opl = Opl::new();
+        opl.write(0x01, 0x20);
+        opl.set_instrument(0, &half_sine);
+        opl.write(0xA0, 0x41);
+        opl.write(0xB0, 0x32);
+        assert!(opl.generate(200).iter().all(|&s| s >= -1));
+    }
+
+    #[test]
+    fn adlib_sound_matches_reference() {
+        let sound = AdlibSound {
+            priority: 0,
+            instrument: Instrument {
+                m_scale: 0x10,
+                c_sus: 0x22,
+                m_attack: 0xD2,
+                c_attack: 0xF3,
+                ..SINE
+            },
+            block: 4,
+            data: vec![0x80, 0x90, 0x00, 0xA0],
+        };
+        let pcm = render_adlib_sound(&sound);
+        assert_eq!(OPL_RATE, pcm.rate);
+        assert_eq!(
+            4 * OPL_RATE as usize / SFX_TICK_RATE as usize,
+            pcm.samples.len()
+        );
+
+        let reference = [
+            12, 3066, 563, 1760, -3597, 2156, 3944, -3999, -1790, -62, 1699, -2301, 3922, 2912,
+            -2199,
+        ];
+        let every_100th: Vec<i16> = pcm.samples.iter().step_by(100).copied().collect();
+        assert_eq!(reference.to_vec(), every_100th);
+        assert_eq!(pcm.samples, render_adlib_sound(&sound).samples);
+    }
+
+    #[test]
+    fn imf_song_lasts_its_delays() {
+        let song = ImfSong {
+            commands: vec![
+                imf(0x20, 0x21, 0),
+                imf(0x43, 0x00, 0),
+                imf(0x63, 0xF0, 0),
+                imf(0xA0, 0x41, 0),
+                imf(0xB0, 0x32, 350),
+                imf(0xB0, 0x12, 350),
+            ],
+        };
+        let pcm = render_imf(&song);
+        assert_eq!(OPL_RATE as usize, pcm.samples.len());
+        assert!(pcm.samples[..OPL_RATE as usize / 2]
+            .iter()
+            .any(|&s| s.abs() > 1000));
+    }
+}
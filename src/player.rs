@@ -1,7 +1,7 @@
 use crate::constants;
 use crate::constants::{MAP_SCALE_H, MAP_SCALE_W};
-use crate::map;
-use crate::map::Tile;
+use crate::door::Doors;
+use crate::map::{self, Bonus};
 use std::f64::consts::PI;
 
 const ROTATE_SPEED: f64 = 0.02;
@@ -28,14 +28,83 @@ pub struct Player {
     pub y: f64,
     pub view_angle: f64,
     pub move_angle: f64,
+    pub keys: Keys,
+}
+
+/// The keys picked up in the current level.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Keys {
+    pub gold: bool,
+    pub silver: bool,
+}
+
+impl Keys {
+    /// Whether these keys open a door with the given lock. Locks 1 and 2 take the gold and
+    /// silver keys, 3 and 4 take keys the game doesn't have.
+    pub fn open(&self, lock: u16) -> bool {
+        match lock {
+            1 => self.gold,
+            2 => self.silver,
+            3 | 4 => false,
+            _ => true,
+        }
+    }
+
+    /// Takes the key a bonus gives, returns false if it's not a key.
+    pub fn pick_up(&mut self, bonus: Bonus) -> bool {
+        match bonus {
+            Bonus::GoldKey => self.gold = true,
+            Bonus::SilverKey => self.silver = true,
+            _ => return false,
+        }
+        true
+    }
 }
 
 impl Player {
-    /// Moves player across the map and prevents stepping into walls.
+    /// The tile the player is on.
+    pub fn tile(&self) -> (usize, usize) {
+        (
+            (self.x / MAP_SCALE_W as f64) as usize,
+            (self.y / MAP_SCALE_H as f64) as usize,
+        )
+    }
+
+    /// The direction of the map closest to the one the player is looking in.
+    pub fn facing(&self) -> map::Direction {
+        let (dx, dy) = (self.view_angle.sin(), self.view_angle.cos());
+        if dx.abs() > dy.abs() {
+            if dx > 0.0 {
+                map::Direction::East
+            } else {
+                map::Direction::West
+            }
+        } else if dy > 0.0 {
+            map::Direction::South
+        } else {
+            map::Direction::North
+        }
+    }
+
+    /// The tile next to the player in the direction they're looking, the one the use key
+    /// acts on.
+    pub fn facing_tile(&self) -> (usize, usize) {
+        let (x, y) = self.tile();
+        match self.facing() {
+            map::Direction::North => (x, y.wrapping_sub(1)),
+            map::Direction::East => (x + 1, y),
+            map::Direction::South => (x, y + 1),
+            map::Direction::West => (x.wrapping_sub(1), y),
+        }
+    }
+
+    /// Moves player across the map and prevents stepping into walls and doors that aren't
+    /// fully open.
     /// Player collision box is a square. Its vertices are checked for collision with nearby walls.
     pub fn walk(
         &mut self,
         map: &map::Map,
+        doors: &Doors,
         straight: Option<StraightMovement>,
         side: Option<SideMovement>,
         turn: Option<TurnMovement>,
@@ -79,6 +148,8 @@ impl Player {
             let new_map_x = new_x / MAP_SCALE_W as f64;
             let new_map_y = new_y / MAP_SCALE_H as f64;
 
+            let blocked = |x: usize, y: usize| map.tile_at(x, y).is_solid() || doors.blocks(x, y);
+
             // directional collision width for player (map scale)
             let collision_offset_x =
                 self.move_angle.sin().signum() * PLAYER_WIDTH / MAP_SCALE_W as f64;
@@ -90,12 +161,9 @@ impl Player {
             │o│
             └─┘
             */
-            let is_collision_slide_x = matches!(
-                map.tile_at(
-                    (new_map_x + collision_offset_x) as u8,
-                    (new_map_y - collision_offset_y) as u8,
-                ),
-                Tile::Wall(_)
+            let is_collision_slide_x = blocked(
+                (new_map_x + collision_offset_x) as usize,
+                (new_map_y - collision_offset_y) as usize,
             );
 
             /* ■ - player angle, ╬ - checked collision vertex of player's box, o - player core position
@@ -103,12 +171,9 @@ impl Player {
             │o│
             ╬─┘
             */
-            let is_collision_slide_y = matches!(
-                map.tile_at(
-                    (new_map_x - collision_offset_x) as u8,
-                    (new_map_y + collision_offset_y) as u8,
-                ),
-                Tile::Wall(_)
+            let is_collision_slide_y = blocked(
+                (new_map_x - collision_offset_x) as usize,
+                (new_map_y + collision_offset_y) as usize,
             );
 
             /* ■ - player angle and checked collision vertex of player's box, o - player core position
@@ -116,12 +181,9 @@ impl Player {
             │o│
             └─┘
             */
-            let is_collision_both = matches!(
-                map.tile_at(
-                    (new_map_x + collision_offset_x) as u8,
-                    (new_map_y + collision_offset_y) as u8,
-                ),
-                Tile::Wall(_)
+            let is_collision_both = blocked(
+                (new_map_x + collision_offset_x) as usize,
+                (new_map_y + collision_offset_y) as usize,
             );
 
             // keep moving/sliding until only both axis are colliding
@@ -166,3 +228,40 @@ impl Player {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_open_their_locks() {
+        let mut keys = Keys::default();
+        assert!(keys.open(0));
+        assert!(!keys.open(1));
+        assert!(!keys.pick_up(Bonus::Food));
+        assert!(keys.pick_up(Bonus::SilverKey));
+        assert!(!keys.open(1));
+        assert!(keys.open(2));
+        assert!(keys.pick_up(Bonus::GoldKey));
+        assert!(keys.open(1));
+        assert!(!keys.open(3));
+        // elevator doors
+        assert!(keys.open(5));
+    }
+
+    #[test]
+    fn facing_rounds_to_the_closest_direction() {
+        let mut player = Player {
+            x: 2.5 * MAP_SCALE_W as f64,
+            y: 2.5 * MAP_SCALE_H as f64,
+            view_angle: constants::ANGLE_UP + 0.5,
+            move_angle: 0.0,
+            keys: Keys::default(),
+        };
+        assert_eq!(map::Direction::North, player.facing());
+        assert_eq!((2, 1), player.facing_tile());
+        player.view_angle = constants::ANGLE_RIGHT - 0.5;
+        assert_eq!(map::Direction::East, player.facing());
+        assert_eq!((3, 2), player.facing_tile());
+    }
+}
@@ -0,0 +1,189 @@
+// Secret walls that slide away when pushed. Like in the original, only one moves at a
+// time: it slides 2 tiles away from the player, taking 128 tics per tile, and stops early
+// if something is in the way. The wall is kept in the walls plane as it moves, on the
+// tile it leaves and on the one it enters, so it stays solid, and the map is left with
+// the wall where it stopped.
+
+use crate::map::{Actor, Direction, Map, Tile};
+
+const TICS_PER_TILE: f64 = 128.0;
+/// How many tiles a push wall moves.
+const DISTANCE: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushWall {
+    /// The tile the wall is moving out of.
+    pub x: usize,
+    pub y: usize,
+    pub direction: Direction,
+    /// The plane 0 code of the wall, which picks its texture.
+    pub tile: u16,
+    /// The plane 0 code to leave behind, the floor of the tile it's moving into.
+    floor: u16,
+    /// Tics since the wall started moving.
+    tics: f64,
+}
+
+impl PushWall {
+    /// Starts pushing the wall at the given position. Returns `None` if it's not a push
+    /// wall, or if the tile behind it isn't free.
+    pub fn push(map: &mut Map, x: usize, y: usize, direction: Direction) -> Option<PushWall> {
+        if map.actor_at(x, y) != Some(Actor::PushWall) {
+            return None;
+        }
+        let tile = match map.tile_at(x, y) {
+            Tile::Wall(tile) => tile,
+            _ => return None,
+        };
+        let (next_x, next_y) = next_tile(map, x, y, direction)?;
+
+        // the wall can't be pushed again
+        map.set_actor_code(x, y, 0);
+        let floor = map.tile_code(next_x, next_y);
+        map.set_tile_code(next_x, next_y, tile);
+        Some(PushWall {
+            x,
+            y,
+            direction,
+            tile,
+            floor,
+            tics: 0.0,
+        })
+    }
+
+    /// How far the wall moved out of its tile, from 0 to 1.
+    pub fn offset(&self) -> f64 {
+        (self.tics % TICS_PER_TILE) / TICS_PER_TILE
+    }
+
+    /// The position of the top left corner of the wall, in tiles.
+    pub fn position(&self) -> (f64, f64) {
+        let (dx, dy) = delta(self.direction);
+        (
+            self.x as f64 + dx as f64 * self.offset(),
+            self.y as f64 + dy as f64 * self.offset(),
+        )
+    }
+
+    /// Whether the wall is partly on the given tile.
+    pub fn covers(&self, x: usize, y: usize) -> bool {
+        let (dx, dy) = delta(self.direction);
+        (x, y) == (self.x, self.y)
+            || (x as isize, y as isize) == (self.x as isize + dx, self.y as isize + dy)
+    }
+
+    /// Moves the wall along for the time elapsed. Returns false once it stopped.
+    pub fn update(&mut self, map: &mut Map, tics: f64) -> bool {
+        let old_block = (self.tics / TICS_PER_TILE) as usize;
+        self.tics += tics;
+        let block = (self.tics / TICS_PER_TILE) as usize;
+
+        for moved in old_block + 1..=block {
+            // the wall is all the way into the next tile
+            let (dx, dy) = delta(self.direction);
+            map.set_tile_code(self.x, self.y, self.floor);
+            self.x = (self.x as isize + dx) as usize;
+            self.y = (self.y as isize + dy) as usize;
+
+            let next = next_tile(map, self.x, self.y, self.direction);
+            match next {
+                Some((next_x, next_y)) if moved < DISTANCE => {
+                    self.floor = map.tile_code(next_x, next_y);
+                    map.set_tile_code(next_x, next_y, self.tile);
+                }
+                _ => {
+                    self.tics = block as f64 * TICS_PER_TILE;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+fn delta(direction: Direction) -> (isize, isize) {
+    match direction {
+        Direction::North => (0, -1),
+        Direction::East => (1, 0),
+        Direction::South => (0, 1),
+        Direction::West => (-1, 0),
+    }
+}
+
+/// The tile next to the given one in `direction`, if the wall can move into it: it must be
+/// floor with nothing on it in the way.
+fn next_tile(map: &Map, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+    let (dx, dy) = delta(direction);
+    let x = x.checked_add_signed(dx)?;
+    let y = y.checked_add_signed(dy)?;
+    if x >= map.width() || y >= map.height() {
+        return None;
+    }
+    let floor = matches!(map.tile_at(x, y), Tile::Floor { .. } | Tile::AmbushFloor);
+    let in_the_way = match map.actor_at(x, y) {
+        Some(Actor::Static(object)) => object.is_blocking(),
+        Some(_) => true,
+        None => false,
+    };
+    if floor && !in_the_way {
+        Some((x, y))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A corridor going east, with a push wall at its west end and a barrel at `barrel`.
+    fn corridor(barrel: usize) -> Map {
+        let mut plane1 = vec![0; 6];
+        plane1[0] = 98;
+        plane1[barrel] = 24;
+        Map::new(
+            6,
+            1,
+            vec![5, 108, 109, 110, 111, 112],
+            plane1,
+            "Row".to_string(),
+        )
+    }
+
+    fn codes(map: &Map) -> Vec<u16> {
+        (0..map.width()).map(|x| map.tile_code(x, 0)).collect()
+    }
+
+    #[test]
+    fn push_walls_move_two_tiles() {
+        let mut map = corridor(5);
+        assert!(PushWall::push(&mut map, 1, 0, Direction::East).is_none());
+        assert!(PushWall::push(&mut map, 0, 0, Direction::West).is_none());
+        let mut wall = PushWall::push(&mut map, 0, 0, Direction::East).unwrap();
+        assert_eq!(vec![5, 5, 109, 110, 111, 112], codes(&map));
+        // it can't be pushed twice
+        assert_eq!(None, map.actor_at(0, 0));
+
+        assert!(wall.update(&mut map, 64.0));
+        assert_eq!((0.5, 0.0), wall.position());
+        assert!(wall.covers(0, 0) && wall.covers(1, 0) && !wall.covers(2, 0));
+        assert!(wall.update(&mut map, 64.0));
+        assert_eq!((1, 0), (wall.x, wall.y));
+        assert_eq!(vec![108, 5, 5, 110, 111, 112], codes(&map));
+
+        assert!(!wall.update(&mut map, 130.0));
+        assert_eq!((2, 0), (wall.x, wall.y));
+        assert_eq!(vec![108, 109, 5, 110, 111, 112], codes(&map));
+    }
+
+    #[test]
+    fn push_walls_stop_on_blocking_objects() {
+        let mut map = corridor(2);
+        let mut wall = PushWall::push(&mut map, 0, 0, Direction::East).unwrap();
+        assert!(!wall.update(&mut map, 128.0));
+        assert_eq!(vec![108, 5, 109, 110, 111, 112], codes(&map));
+
+        let mut map = corridor(1);
+        assert!(PushWall::push(&mut map, 0, 0, Direction::East).is_none());
+    }
+}
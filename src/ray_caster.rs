@@ -1,6 +1,8 @@
 use crate::constants::*;
-use crate::map::{Map, Tile};
+use crate::door::Doors;
+use crate::map::{Map, Tile, ELEVATOR_TILE, FAKE_ELEVATOR_TILE};
 use crate::player::Player;
+use crate::push_wall::PushWall;
 use num::pow;
 use std::cmp::min;
 use std::f64::consts::PI;
@@ -14,34 +16,68 @@ const TILE_SIZE: f64 = 4.8;
 // FIXME this is suspicious, probably use Option or Result?
 struct Nothing;
 
+/// What a ray stopped on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Surface {
+    /// A wall, by its plane 0 code.
+    Wall(u16),
+    /// The door at the given tile. Rays hit doors halfway through their tile.
+    Door { x: usize, y: usize },
+}
+
 pub struct RayHit {
     pub height: u32,
-    pub tile: u16,
+    pub surface: Surface,
     pub horizontal: bool,
     pub tex_x: usize,
 }
 
-pub fn draw_rays(n_rays: u32, height: u32, map: &Map, player: &Player) -> Vec<RayHit> {
+pub fn draw_rays(
+    n_rays: u32,
+    height: u32,
+    map: &Map,
+    doors: &Doors,
+    push_wall: Option<&PushWall>,
+    player: &Player,
+) -> Vec<RayHit> {
     let fov_delta = FIELD_OF_VIEW / (n_rays as f64);
     let mut hits: Vec<RayHit> = Vec::new();
     for i in 0..n_rays {
         let fov_angle = fov_delta * (i as f64);
         // transformation from cylindrical screen to flat screen (prevents fisheye effect)
         let offset = (FIELD_OF_VIEW / 2.0 - fov_angle).atan();
-        let ray_h = cast_ray_h(map, player, offset);
-        let ray_v = cast_ray_v(map, player, offset);
+        let ray_h = cast_ray_h(map, doors, push_wall, player, offset);
+        let ray_v = cast_ray_v(map, doors, push_wall, player, offset);
         let (hit, horiz) = match (ray_h, ray_v) {
             ((_, _, d1, _), (_, _, d2, _)) if d1 <= d2 => (ray_h, false),
             _ => (ray_v, true),
         };
-        let (_, _, distance, tile) = hit;
+        let (_, _, mut distance, mut surface) = hit;
+        let mut horiz = horiz;
+        let mut tex_x = match surface {
+            Surface::Wall(_) => ray_to_tex_coordinatinates(hit.0, hit.1, horiz),
+            Surface::Door { x, y } => door_tex_x(doors, x, y, hit.0, hit.1, horiz),
+        };
+
+        // the moving push wall is in front of whatever the ray hit behind it
+        if let Some(wall) = push_wall {
+            let ray_angle = norm_angle(player.view_angle + offset);
+            match cast_ray_push_wall(wall, player, ray_angle) {
+                Some((wall_distance, wall_horiz, wall_tex_x)) if wall_distance < distance => {
+                    distance = wall_distance;
+                    surface = Surface::Wall(wall.tile);
+                    horiz = wall_horiz;
+                    tex_x = wall_tex_x;
+                }
+                _ => {}
+            }
+        }
 
         let adj_distance = distance * offset.cos();
         let ray_height = TILE_SIZE * n_rays as f64 / adj_distance;
-        let tex_x = ray_to_tex_coordinatinates(hit.0, hit.1, horiz);
         hits.push(RayHit {
             height: min(height, ray_height as u32),
-            tile,
+            surface,
             horizontal: horiz,
             tex_x,
         });
@@ -50,12 +86,18 @@ pub fn draw_rays(n_rays: u32, height: u32, map: &Map, player: &Player) -> Vec<Ra
 }
 
 //canvas parameter left here to facilitate debug drawings
-fn cast_ray_v(map: &Map, player: &Player, ray_offset: f64) -> (f64, f64, f64, u16) {
+fn cast_ray_v(
+    map: &Map,
+    doors: &Doors,
+    push_wall: Option<&PushWall>,
+    player: &Player,
+    ray_offset: f64,
+) -> (f64, f64, f64, Surface) {
     let ray_angle = norm_angle(player.view_angle + ray_offset);
 
     //looking to the side -- cannot hit a horizontal line
     if ray_angle == ANGLE_LEFT || ray_angle == ANGLE_RIGHT {
-        return (0.0, 0.0, f64::INFINITY, 0);
+        return (0.0, 0.0, f64::INFINITY, Surface::Wall(0));
     }
 
     let (rx, ry, xo, yo) = if !(ANGLE_RIGHT..=ANGLE_LEFT).contains(&ray_angle) {
@@ -69,22 +111,24 @@ fn cast_ray_v(map: &Map, player: &Player, ray_offset: f64) -> (f64, f64, f64, u1
         let a = player.y - round_y;
         let b = a * ray_angle.tan();
         let c = MAP_SCALE_H as f64 * ray_angle.tan();
-        (
-            player.x - b,
-            round_y - 0.000001,
-            -1.0 * c,
-            -1.0 * MAP_SCALE_H as f64,
-        )
+        (player.x - b, round_y - 0.000001, -c, -(MAP_SCALE_H as f64))
     };
-    follow_ray(map, player, rx, ry, xo, yo)
+    // these rays cross the rows of the map, so they can hit horizontal doors
+    follow_ray(map, doors, push_wall, false, player, rx, ry, xo, yo)
 }
 
-fn cast_ray_h(map: &Map, player: &Player, ray_offset: f64) -> (f64, f64, f64, u16) {
+fn cast_ray_h(
+    map: &Map,
+    doors: &Doors,
+    push_wall: Option<&PushWall>,
+    player: &Player,
+    ray_offset: f64,
+) -> (f64, f64, f64, Surface) {
     let ray_angle = norm_angle(player.view_angle + ray_offset);
 
     //looking up/down -- cannot hit a vertical line
     if ray_angle == ANGLE_UP || ray_angle == ANGLE_DOWN {
-        return (0.0, 0.0, f64::INFINITY, 0);
+        return (0.0, 0.0, f64::INFINITY, Surface::Wall(0));
     }
 
     let (rx, ry, xo, yo) = if ray_angle < ANGLE_UP {
@@ -99,49 +143,132 @@ fn cast_ray_h(map: &Map, player: &Player, ray_offset: f64) -> (f64, f64, f64, u1
         let b = player.x - round_x;
         let a = b / ray_angle.tan();
         let c = MAP_SCALE_W as f64 / ray_angle.tan();
-        (
-            round_x - 0.00001,
-            player.y - a,
-            -1.0 * MAP_SCALE_W as f64,
-            -1.0 * c,
-        )
+        (round_x - 0.00001, player.y - a, -(MAP_SCALE_W as f64), -c)
     };
-    follow_ray(map, player, rx, ry, xo, yo)
+    follow_ray(map, doors, push_wall, true, player, rx, ry, xo, yo)
 }
 
+/// Follows a ray from grid line to grid line until it hits a wall, or a door of the
+/// orientation the lines it crosses can hit, halfway to the next line. The tiles of a
+/// moving push wall are see-through, the wall itself is hit by `cast_ray_push_wall`.
+#[allow(clippy::too_many_arguments)]
 fn follow_ray(
     map: &Map,
+    doors: &Doors,
+    push_wall: Option<&PushWall>,
+    vertical_doors: bool,
     player: &Player,
     x: f64,
     y: f64,
     xo: f64,
     yo: f64,
-) -> (f64, f64, f64, u16) {
+) -> (f64, f64, f64, Surface) {
     let (mut rx, mut ry) = (x, y);
-    for _ in 1..MAP_HEIGHT {
+    let moving = |x: f64, y: f64| {
+        push_wall
+            .is_some_and(|wall| wall.covers(cdiv(x, MAP_SCALE_W, 0.0), cdiv(y, MAP_SCALE_H, 0.0)))
+    };
+    for _ in 1..map.width().max(map.height()) {
         match read_map(map, rx, ry) {
+            Ok(Tile::Wall(_)) if moving(rx, ry) => {}
             Ok(Tile::Wall(tile)) => {
-                return (rx, ry, distance(player, rx, ry), tile);
+                return (rx, ry, distance(player, rx, ry), Surface::Wall(tile));
+            }
+            Ok(Tile::Elevator) => {
+                return (
+                    rx,
+                    ry,
+                    distance(player, rx, ry),
+                    Surface::Wall(ELEVATOR_TILE),
+                );
+            }
+            Ok(Tile::FakeElevator) => {
+                return (
+                    rx,
+                    ry,
+                    distance(player, rx, ry),
+                    Surface::Wall(FAKE_ELEVATOR_TILE),
+                );
             }
             Err(_) => {
-                return (rx, ry, distance(player, rx, ry), 0);
+                return (rx, ry, distance(player, rx, ry), Surface::Wall(0));
             }
             _ => {}
         }
+
+        let (mx, my) = (rx + xo / 2.0, ry + yo / 2.0);
+        if let Ok(Tile::Door { vertical, .. }) = read_map(map, mx, my) {
+            let (tx, ty) = (cdiv(mx, MAP_SCALE_W, 0.0), cdiv(my, MAP_SCALE_H, 0.0));
+            // the part of the door that slid away lets the ray through
+            let along = if vertical {
+                (my / MAP_SCALE_H as f64).fract()
+            } else {
+                (mx / MAP_SCALE_W as f64).fract()
+            };
+            if vertical == vertical_doors && along >= doors.open_amount(tx, ty) {
+                return (
+                    mx,
+                    my,
+                    distance(player, mx, my),
+                    Surface::Door { x: tx, y: ty },
+                );
+            }
+        }
+
         rx += xo;
         ry += yo;
     }
 
-    (rx, ry, distance(player, rx, ry), 0)
+    (rx, ry, distance(player, rx, ry), Surface::Wall(0))
+}
+
+/// Where a ray hits the moving push wall, as the distance, whether it hit one of the
+/// horizontal sides and the x-coordinate within the texture. The wall is a tile sized
+/// box, so the ray is clipped against its sides.
+fn cast_ray_push_wall(
+    wall: &PushWall,
+    player: &Player,
+    ray_angle: f64,
+) -> Option<(f64, bool, usize)> {
+    let (dx, dy) = (ray_angle.sin(), ray_angle.cos());
+    let (wx, wy) = wall.position();
+    let (left, top) = (wx * MAP_SCALE_W as f64, wy * MAP_SCALE_H as f64);
+    let (right, bottom) = (left + MAP_SCALE_W as f64, top + MAP_SCALE_H as f64);
+
+    // how far along the ray it crosses the lines of the sides
+    let slab = |start: f64, end: f64, from: f64, d: f64| {
+        let (t1, t2) = ((start - from) / d, (end - from) / d);
+        (t1.min(t2), t1.max(t2))
+    };
+    let (x_in, x_out) = slab(left, right, player.x, dx);
+    let (y_in, y_out) = slab(top, bottom, player.y, dy);
+    let (t_in, t_out) = (x_in.max(y_in), x_out.min(y_out));
+    if t_in > t_out || t_in <= 0.0 {
+        return None;
+    }
+
+    let horizontal = y_in > x_in;
+    let u = (player.x + dx * t_in - left) / MAP_SCALE_W as f64;
+    let v = (player.y + dy * t_in - top) / MAP_SCALE_H as f64;
+    // the same orientation as the textures of the walls the grid lines hit
+    let forward = if horizontal { dy > 0.0 } else { dx > 0.0 };
+    let fract = match (horizontal, forward) {
+        (true, true) => 1.0 - u,
+        (true, false) => u,
+        (false, true) => v,
+        (false, false) => 1.0 - v,
+    };
+    let tex_x = ((fract.clamp(0.0, 1.0) * WALLPIC_WIDTH as f64) as usize).min(WALLPIC_WIDTH - 1);
+    Some((t_in, horizontal, tex_x))
 }
 
 fn read_map(map: &Map, x: f64, y: f64) -> Result<Tile, Nothing> {
     let mx = cdiv(x, MAP_SCALE_W, 0.0);
     let my = cdiv(y, MAP_SCALE_H, 0.0);
-    if mx >= MAP_WIDTH || my >= MAP_HEIGHT {
+    if mx >= map.width() || my >= map.height() {
         Err(Nothing)
     } else {
-        Ok(map.tile_at(mx as u8, my as u8))
+        Ok(map.tile_at(mx, my))
     }
 }
 
@@ -168,6 +295,17 @@ fn ray_to_tex_coordinatinates(rx: f64, ry: f64, horizontal: bool) -> usize {
     (fract * WALLPIC_WIDTH as f64) as usize
 }
 
+/// Where the ray hit the door within its texture, which slides along with the door.
+fn door_tex_x(doors: &Doors, x: usize, y: usize, rx: f64, ry: f64, horizontal: bool) -> usize {
+    let along = if horizontal {
+        (rx / MAP_SCALE_W as f64).fract()
+    } else {
+        (ry / MAP_SCALE_H as f64).fract()
+    };
+    let fract = (along - doors.open_amount(x, y)).max(0.0);
+    ((fract * WALLPIC_WIDTH as f64) as usize).min(WALLPIC_WIDTH - 1)
+}
+
 fn cdiv(x: f64, scale: u32, updown: f64) -> usize {
     (x / scale as f64 + updown).trunc() as usize
 }
@@ -179,3 +317,79 @@ fn ctrunc(x: f64, scale: u32, updown: f64) -> f64 {
 fn distance(player: &Player, x: f64, y: f64) -> f64 {
     (pow(x - player.x, 2) + pow(y - player.y, 2)).sqrt()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map;
+
+    /// A corridor going east, with a vertical door halfway.
+    fn corridor() -> Map {
+        let mut plane0 = vec![1; 8 * 3];
+        plane0[8 + 1..8 + 7].copy_from_slice(&[108, 108, 90, 108, 108, 108]);
+        Map::new(8, 3, plane0, vec![0; 8 * 3], "Corridor".to_string())
+    }
+
+    fn player_looking_east() -> Player {
+        Player {
+            x: 1.5 * MAP_SCALE_W as f64,
+            y: 1.5 * MAP_SCALE_H as f64,
+            view_angle: ANGLE_RIGHT,
+            move_angle: ANGLE_RIGHT,
+            keys: Default::default(),
+        }
+    }
+
+    #[test]
+    fn rays_stop_halfway_through_closed_doors() {
+        let map = corridor();
+        let doors = Doors::new(&map);
+        // with 2 rays the second one goes straight ahead
+        let hit = &draw_rays(2, 200, &map, &doors, None, &player_looking_east())[1];
+        assert_eq!(Surface::Door { x: 3, y: 1 }, hit.surface);
+        assert_eq!(WALLPIC_WIDTH / 2, hit.tex_x);
+
+        let (_, _, distance, _) = cast_ray_h(&map, &doors, None, &player_looking_east(), 0.0);
+        assert!((distance - 2.0 * MAP_SCALE_W as f64).abs() < 0.01);
+    }
+
+    #[test]
+    fn rays_go_through_open_doors() {
+        let map = corridor();
+        let mut doors = Doors::new(&map);
+        doors.operate(3, 1, Default::default());
+        doors.update(32.0, (1, 1));
+        // the door slid halfway, the middle of the tile is the edge of the door
+        let hit = &draw_rays(2, 200, &map, &doors, None, &player_looking_east())[1];
+        assert_eq!(Surface::Door { x: 3, y: 1 }, hit.surface);
+        assert_eq!(0, hit.tex_x);
+
+        doors.update(32.0, (1, 1));
+        let hit = &draw_rays(2, 200, &map, &doors, None, &player_looking_east())[1];
+        assert_eq!(Surface::Wall(1), hit.surface);
+    }
+
+    #[test]
+    fn rays_hit_push_walls_partway_through_a_tile() {
+        let mut plane0 = vec![1; 8 * 3];
+        plane0[8 + 1..8 + 7].copy_from_slice(&[108, 108, 5, 108, 108, 108]);
+        let mut plane1 = vec![0; 8 * 3];
+        plane1[8 + 3] = 98;
+        let mut map = Map::new(8, 3, plane0, plane1, "Secret".to_string());
+        let doors = Doors::new(&map);
+        let player = player_looking_east();
+
+        let mut wall = PushWall::push(&mut map, 3, 1, map::Direction::East).unwrap();
+        wall.update(&mut map, 64.0);
+        let hit = &draw_rays(2, 200, &map, &doors, Some(&wall), &player)[1];
+        assert_eq!(Surface::Wall(5), hit.surface);
+        assert!(!hit.horizontal);
+        assert_eq!(WALLPIC_WIDTH / 2, hit.tex_x);
+        let (distance, _, _) = cast_ray_push_wall(&wall, &player, ANGLE_RIGHT).unwrap();
+        assert!((distance - 2.0 * MAP_SCALE_W as f64).abs() < 0.01);
+
+        // the tiles it moves through don't stop the rays themselves
+        let (_, _, _, surface) = cast_ray_h(&map, &doors, Some(&wall), &player, 0.0);
+        assert_eq!(Surface::Wall(1), surface);
+    }
+}
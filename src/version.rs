@@ -0,0 +1,373 @@
+// Tells the releases of the game apart. Releases of the same variant ship data files with
+// different contents and, what matters to us, a different number of pictures in VGAGRAPH,
+// which moves the chunks that come after them. The layout is all the game needs to start,
+// so it's all that's checked at startup; hashing every file is left to `info`, which then
+// names the release by the size and CRC32 of its files.
+
+use crate::cache::{self, CacheError, ChunkLayout, GameVariant, GRAPHICS_OFFSET_SIZE};
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// A data file of a release, as it was shipped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct KnownFile {
+    /// Base name of the file, e.g. "VSWAP".
+    pub name: &'static str,
+    pub size: usize,
+    pub crc32: u32,
+}
+
+/// A release whose data files we know how to read.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Release {
+    pub name: &'static str,
+    pub variant: GameVariant,
+    pub layout: ChunkLayout,
+    /// The files the release is recognised by, as `info` prints them. Releases without
+    /// any are only known by their layout, so they can't be named.
+    pub files: &'static [KnownFile],
+}
+
+/// The releases the chunk layouts in `cache` have been checked against, one per layout.
+/// Their files aren't recorded, so `identify` only names releases given theirs, and
+/// `info` reports which of these a data set is laid out like.
+const KNOWN_RELEASES: [Release; 3] = [
+    Release {
+        name: "Wolfenstein 3D shareware v1.4",
+        variant: GameVariant::Shareware,
+        layout: ChunkLayout::SHAREWARE,
+        files: &[],
+    },
+    // Apogee's and Activision's v1.4 share this layout, one entry stands for both
+    Release {
+        name: "Wolfenstein 3D v1.4",
+        variant: GameVariant::Registered,
+        layout: ChunkLayout::REGISTERED,
+        files: &[],
+    },
+    Release {
+        name: "Spear of Destiny v1.0",
+        variant: GameVariant::SpearOfDestiny,
+        layout: ChunkLayout::SPEAR_OF_DESTINY,
+        files: &[],
+    },
+];
+
+#[derive(Debug)]
+pub struct FileFingerprint {
+    pub name: &'static str,
+    pub path: PathBuf,
+    pub size: usize,
+    /// Only known once `Fingerprint::checksum` read the file.
+    pub crc32: Option<u32>,
+}
+
+/// Releases older than these lay their graphics out in ways the chunk constants in
+/// `cache` don't follow, and they are refused rather than guessed at.
+const UNSUPPORTED_RELEASES: &str = "v1.0 to v1.2";
+
+/// What identifies a data set: its files and how its graphics chunks are laid out.
+#[derive(Debug)]
+pub struct Fingerprint {
+    pub variant: GameVariant,
+    pub files: Vec<FileFingerprint>,
+    /// Number of chunks listed in VGAHEAD.
+    pub graphics_chunks: usize,
+    /// Number of pictures, from the size of the picture table at the start of VGAGRAPH.
+    pub num_pics: usize,
+}
+
+#[derive(Debug)]
+pub struct UnknownRelease {
+    pub variant: GameVariant,
+    pub num_pics: usize,
+    pub graphics_chunks: usize,
+}
+
+impl fmt::Display for UnknownRelease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let known: Vec<&str> = KNOWN_RELEASES.iter().map(|release| release.name).collect();
+        write!(
+            f,
+            "unknown {} release, with {} pictures in {} graphics chunks; the supported releases are: {} (releases {} aren't supported)",
+            self.variant.extension(),
+            self.num_pics,
+            self.graphics_chunks,
+            known.join(", "),
+            UNSUPPORTED_RELEASES
+        )
+    }
+}
+
+impl error::Error for UnknownRelease {}
+
+/// Takes the fingerprint of the data files of `variant` in `data_dir`, without reading
+/// them through: only their sizes and the graphics headers.
+pub fn fingerprint(data_dir: &Path, variant: GameVariant) -> Result<Fingerprint, CacheError> {
+    let mut files = Vec::with_capacity(cache::DATA_FILES.len());
+    let mut graphics_chunks = 0;
+    let mut num_pics = 0;
+    for name in cache::DATA_FILES {
+        let path = cache::data_file(data_dir, name, variant);
+        let size = match fs::metadata(&path) {
+            Ok(metadata) => metadata.len() as usize,
+            Err(source) => return Err(CacheError::MissingFile { path, source }),
+        };
+        match name {
+            "VGAHEAD" => {
+                graphics_chunks = (size / GRAPHICS_OFFSET_SIZE).saturating_sub(1);
+            }
+            "VGAGRAPH" => {
+                // the picture table is the first chunk, it starts with its length
+                let mut table_len = [0; 4];
+                File::open(&path)
+                    .and_then(|mut file| file.read_exact(&mut table_len))
+                    .map_err(|_| CacheError::TruncatedChunk {
+                        path: path.clone(),
+                        offset: 0,
+                        length: 4,
+                    })?;
+                num_pics = u32::from_le_bytes(table_len) as usize / 4;
+            }
+            _ => {}
+        }
+        files.push(FileFingerprint {
+            name,
+            path,
+            size,
+            crc32: None,
+        });
+    }
+
+    Ok(Fingerprint {
+        variant,
+        files,
+        graphics_chunks,
+        num_pics,
+    })
+}
+
+/// The CRC32 of a file, read a buffer at a time.
+fn crc32_file(path: &Path) -> std::io::Result<u32> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = crc32fast::Hasher::new();
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(hasher.finalize());
+        }
+        let len = buffer.len();
+        hasher.update(buffer);
+        reader.consume(len);
+    }
+}
+
+impl Fingerprint {
+    /// Hashes every file, to tell which release they're from.
+    pub fn checksum(&mut self) -> Result<(), CacheError> {
+        for file in &mut self.files {
+            let crc32 = crc32_file(&file.path).map_err(|source| CacheError::MissingFile {
+                path: file.path.clone(),
+                source,
+            })?;
+            file.crc32 = Some(crc32);
+        }
+        Ok(())
+    }
+
+    /// The graphics chunk layout of the data set, known release or not. Only the number
+    /// of pictures is known of the layouts no known release has.
+    pub fn layout(&self) -> ChunkLayout {
+        KNOWN_RELEASES
+            .iter()
+            .find(|release| {
+                release.variant == self.variant && release.layout.num_pics == self.num_pics
+            })
+            .map_or(ChunkLayout::new(self.num_pics), |release| release.layout)
+    }
+
+    /// The first known release with the layout of these files, which the game can play
+    /// them as, whichever release they really are from.
+    pub fn compatible_release(&self) -> Result<&'static Release, UnknownRelease> {
+        KNOWN_RELEASES
+            .iter()
+            .find(|release| {
+                release.variant == self.variant
+                    && release.layout.num_pics == self.num_pics
+                    && self.graphics_chunks > release.layout.start_externs()
+            })
+            .ok_or(UnknownRelease {
+                variant: self.variant,
+                num_pics: self.num_pics,
+                graphics_chunks: self.graphics_chunks,
+            })
+    }
+
+    /// The release every file matches the size and checksum of, once `checksum` ran.
+    pub fn identify(&self) -> Option<&'static Release> {
+        self.find_release(&KNOWN_RELEASES)
+    }
+
+    fn find_release<'a>(&self, releases: &'a [Release]) -> Option<&'a Release> {
+        releases.iter().find(|release| {
+            release.variant == self.variant
+                && !release.files.is_empty()
+                && release.files.iter().all(|known| {
+                    self.files.iter().any(|file| {
+                        file.name == known.name
+                            && file.size == known.size
+                            && file.crc32 == Some(known.crc32)
+                    })
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_data_set(dir: &Path, num_pics: u32, graphics_chunks: usize) {
+        fs::create_dir_all(dir).unwrap();
+        for name in cache::DATA_FILES {
+            let data = match name {
+                "VGAHEAD" => vec![0; (graphics_chunks + 1) * GRAPHICS_OFFSET_SIZE],
+                "VGAGRAPH" => (num_pics * 4).to_le_bytes().to_vec(),
+                _ => name.as_bytes().to_vec(),
+            };
+            fs::write(dir.join(format!("{}.WL1", name)), data).unwrap();
+        }
+    }
+
+    #[test]
+    fn fingerprint_has_layout_and_checksums() {
+        let dir = std::env::temp_dir().join("rustenstein_fingerprint_test");
+        write_data_set(&dir, 144, 161);
+
+        let mut fingerprint = fingerprint(&dir, GameVariant::Shareware).unwrap();
+        assert_eq!(144, fingerprint.num_pics);
+        assert_eq!(161, fingerprint.graphics_chunks);
+        assert_eq!(ChunkLayout::SHAREWARE, fingerprint.layout());
+        assert_eq!(cache::DATA_FILES.len(), fingerprint.files.len());
+        let vswap = &fingerprint.files[0];
+        assert_eq!(dir.join("VSWAP.WL1"), vswap.path);
+        assert_eq!((5, None), (vswap.size, vswap.crc32));
+        assert_eq!(
+            "Wolfenstein 3D shareware v1.4",
+            fingerprint.compatible_release().unwrap().name
+        );
+
+        fingerprint.checksum().unwrap();
+        assert_eq!(Some(crc32fast::hash(b"VSWAP")), fingerprint.files[0].crc32);
+        // the files of a real release are something else
+        assert_eq!(None, fingerprint.identify());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn releases_are_told_apart_by_their_files() {
+        let dir = std::env::temp_dir().join("rustenstein_release_files_test");
+        write_data_set(&dir, 144, 161);
+        let mut fingerprint = fingerprint(&dir, GameVariant::Shareware).unwrap();
+        fingerprint.checksum().unwrap();
+
+        let release = |name, crc32| Release {
+            name,
+            variant: GameVariant::Shareware,
+            layout: ChunkLayout::SHAREWARE,
+            files: vec![
+                KnownFile {
+                    name: "VSWAP",
+                    size: 5,
+                    crc32,
+                },
+                KnownFile {
+                    name: "AUDIOT",
+                    size: 6,
+                    crc32: crc32fast::hash(b"AUDIOT"),
+                },
+            ]
+            .leak(),
+        };
+        let releases = [
+            release("other", crc32fast::hash(b"vswap")),
+            release("this one", crc32fast::hash(b"VSWAP")),
+        ];
+        assert_eq!(
+            "this one",
+            fingerprint.find_release(&releases).unwrap().name
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn registered_and_spear_layouts_are_known() {
+        for (dir, variant, num_pics, graphics_chunks) in [
+            (
+                "rustenstein_wl6_layout_test",
+                GameVariant::Registered,
+                132,
+                149,
+            ),
+            (
+                "rustenstein_sod_layout_test",
+                GameVariant::SpearOfDestiny,
+                147,
+                168,
+            ),
+        ] {
+            let dir = std::env::temp_dir().join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            for name in cache::DATA_FILES {
+                let data = match name {
+                    "VGAHEAD" => vec![0; (graphics_chunks + 1) * GRAPHICS_OFFSET_SIZE],
+                    "VGAGRAPH" => (num_pics * 4u32).to_le_bytes().to_vec(),
+                    _ => vec![],
+                };
+                let file = format!("{}.{}", name, variant.extension());
+                fs::write(dir.join(file), data).unwrap();
+            }
+
+            let fingerprint = fingerprint(&dir, variant).unwrap();
+            let release = fingerprint.compatible_release().unwrap();
+            assert_eq!(variant, release.variant);
+            assert_eq!(
+                graphics_chunks,
+                release.layout.start_externs() + release.layout.externs.len()
+            );
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn unknown_layouts_are_refused() {
+        let dir = std::env::temp_dir().join("rustenstein_unknown_release_test");
+        write_data_set(&dir, 132, 149);
+
+        let fingerprint = fingerprint(&dir, GameVariant::Shareware).unwrap();
+        assert_eq!(135, fingerprint.layout().start_tile8());
+        assert!(fingerprint.layout().externs.is_empty());
+        let err = fingerprint.compatible_release().unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("unknown WL1 release, with 132 pictures in 149 graphics chunks"));
+        assert!(err
+            .to_string()
+            .ends_with("(releases v1.0 to v1.2 aren't supported)"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let dir = std::env::temp_dir().join("rustenstein_no_release_test");
+        fs::create_dir_all(&dir).unwrap();
+        assert!(matches!(
+            fingerprint(&dir, GameVariant::Registered),
+            Err(CacheError::MissingFile { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
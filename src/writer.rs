@@ -0,0 +1,703 @@
+// Writes data files the game can read back, the inverse of what `cache` does.
+// On the map files: https://moddingwiki.shikadi.net/wiki/GameMaps_Format
+// On the graphics files: https://moddingwiki.shikadi.net/wiki/VGAGRAPH_Format
+// On VSWAP: https://moddingwiki.shikadi.net/wiki/VSWAP_Format
+
+use crate::cache::{
+    carmack_compress, encode_sprite, huff_compress, huff_dictionary, rlew_compress, Cache,
+    CacheError, ChunkLayout, DigiSound, Sprite, GRAPHICS_OFFSET_SIZE, MAP_LEVEL_HEADER_SIZE,
+};
+use crate::extract::skip_missing;
+use crate::map::Map;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The RLEW tag the original map files use.
+pub const RLEW_TAG: u16 = 0xABCD;
+const MAX_MAPS: usize = 100;
+const NUM_PLANES: usize = 3;
+const GAME_MAPS_SIGNATURE: &[u8] = b"TED5v1.0";
+const LEVEL_SIGNATURE: &[u8] = b"!ID!";
+const NAME_SIZE: usize = 16;
+/// Digitized sounds are split in pages of this size.
+const SOUND_PAGE_SIZE: usize = 4096;
+
+/// Builds the MAPHEAD and GAMEMAPS files holding `maps`, in that order. Each level is
+/// stored as its planes followed by its header, the way TED5 lays them out. A missing
+/// plane 2 gets an offset and length of 0.
+pub fn build_map_files(maps: &[Map]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    if maps.len() > MAX_MAPS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("only {} maps fit in MAPHEAD", MAX_MAPS),
+        ));
+    }
+    let magic = RLEW_TAG.to_le_bytes();
+
+    let mut game_maps = GAME_MAPS_SIGNATURE.to_vec();
+    let mut pointers = Vec::with_capacity(maps.len());
+    for map in maps {
+        let mut offsets = [0i32; NUM_PLANES];
+        let mut lengths = [0u16; NUM_PLANES];
+        for (plane, (offset, length)) in offsets.iter_mut().zip(lengths.iter_mut()).enumerate() {
+            let Some(bytes) = plane_bytes(map, plane) else {
+                continue;
+            };
+            let chunk = compress_plane(&bytes, &magic)?;
+            *offset = game_maps.len() as i32;
+            *length = u16::try_from(chunk.len()).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "plane {} of {} compresses to {} bytes, more than GAMEMAPS can hold",
+                        plane,
+                        map.name,
+                        chunk.len()
+                    ),
+                )
+            })?;
+            game_maps.extend(chunk);
+        }
+
+        pointers.push(game_maps.len() as i32);
+        let mut header = Vec::with_capacity(MAP_LEVEL_HEADER_SIZE);
+        for offset in offsets {
+            header.extend(offset.to_le_bytes());
+        }
+        for length in lengths {
+            header.extend(length.to_le_bytes());
+        }
+        header.extend((map.width() as u16).to_le_bytes());
+        header.extend((map.height() as u16).to_le_bytes());
+        // the name is NUL terminated and padded
+        let mut name = [0u8; NAME_SIZE];
+        let name_bytes = map.name.as_bytes();
+        let name_len = name_bytes.len().min(NAME_SIZE - 1);
+        name[..name_len].copy_from_slice(&name_bytes[..name_len]);
+        header.extend(name);
+        game_maps.extend(header);
+        game_maps.extend(LEVEL_SIGNATURE);
+    }
+
+    let mut map_head = magic.to_vec();
+    for i in 0..MAX_MAPS {
+        map_head.extend(pointers.get(i).copied().unwrap_or(0).to_le_bytes());
+    }
+    Ok((map_head, game_maps))
+}
+
+/// Writes the MAPHEAD and GAMEMAPS files holding `maps` to the given paths.
+pub fn write_map_files(maps: &[Map], map_head: &Path, game_maps: &Path) -> io::Result<()> {
+    let (head_bytes, maps_bytes) = build_map_files(maps)?;
+    fs::File::create(map_head)?.write_all(&head_bytes)?;
+    fs::File::create(game_maps)?.write_all(&maps_bytes)
+}
+
+/// Builds the VGADICT, VGAHEAD and VGAGRAPH files holding the expanded `chunks`, in that
+/// order, with a huffman dictionary made for them. Every chunk but the one with the 8x8
+/// tiles, as told by `layout`, starts with its expanded length.
+pub fn build_graphics_files(
+    chunks: &[Vec<u8>],
+    layout: ChunkLayout,
+) -> io::Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let huff = huff_dictionary(chunks.iter().map(Vec::as_slice));
+    let mut dict = Vec::with_capacity(huff.len() * 4);
+    for (bit0, bit1) in &huff {
+        dict.extend(bit0.to_le_bytes());
+        dict.extend(bit1.to_le_bytes());
+    }
+
+    let mut offsets = Vec::with_capacity(chunks.len() + 1);
+    let mut graph = Vec::new();
+    for (chunk, data) in chunks.iter().enumerate() {
+        offsets.push(graph.len());
+        if chunk != layout.start_tile8() {
+            graph.extend((data.len() as u32).to_le_bytes());
+        }
+        graph.extend(huff_compress(&huff, data));
+    }
+    offsets.push(graph.len());
+
+    if graph.len() >= 1 << (GRAPHICS_OFFSET_SIZE * 8) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the graphics don't fit in the 3 byte offsets of VGAHEAD",
+        ));
+    }
+    let head = offsets
+        .iter()
+        .flat_map(|offset| (*offset as u32).to_le_bytes()[..GRAPHICS_OFFSET_SIZE].to_vec())
+        .collect();
+    Ok((dict, head, graph))
+}
+
+/// Writes the VGADICT, VGAHEAD and VGAGRAPH files holding `chunks` to the given paths.
+pub fn write_graphics_files(
+    chunks: &[Vec<u8>],
+    layout: ChunkLayout,
+    dict: &Path,
+    head: &Path,
+    graph: &Path,
+) -> io::Result<()> {
+    let (dict_bytes, head_bytes, graph_bytes) = build_graphics_files(chunks, layout)?;
+    fs::File::create(dict)?.write_all(&dict_bytes)?;
+    fs::File::create(head)?.write_all(&head_bytes)?;
+    fs::File::create(graph)?.write_all(&graph_bytes)
+}
+
+/// What goes in a VSWAP file. Missing textures and sprites, like the registered version
+/// ones in the shareware data, are left out as sparse pages.
+#[derive(Debug, Default)]
+pub struct VswapContents {
+    /// 64x64 textures, column by column.
+    pub textures: Vec<Option<Vec<u8>>>,
+    pub sprites: Vec<Option<Sprite>>,
+    pub sounds: Vec<DigiSound>,
+}
+
+impl VswapContents {
+    /// The textures, sprites and sounds of a data set, with those of its mod directory
+    /// replacing the original ones.
+    pub fn from_cache(cache: &Cache) -> Result<Self, CacheError> {
+        let mut contents = VswapContents::default();
+        for index in 0..cache.num_textures() {
+            contents
+                .textures
+                .push(skip_missing(cache.get_texture(index))?.cloned());
+        }
+        for index in 0..cache.num_sprites() {
+            contents
+                .sprites
+                .push(skip_missing(cache.get_sprite(index))?.cloned());
+        }
+        for index in 0..cache.num_sounds() {
+            contents.sounds.push(cache.get_sound(index)?.clone());
+        }
+        Ok(contents)
+    }
+}
+
+/// Builds a VSWAP file: the page counts, the offset and length of every page, then the
+/// pages themselves. Textures come first, then sprites encoded as posts, then sounds in
+/// 4KB pages, and last the page listing where every sound starts and its length.
+pub fn build_vswap(contents: &VswapContents) -> io::Result<Vec<u8>> {
+    let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidInput, reason);
+
+    let mut pages: Vec<Option<Vec<u8>>> = Vec::new();
+    pages.extend(contents.textures.iter().cloned());
+    let sprite_start = pages.len();
+    pages.extend(
+        contents
+            .sprites
+            .iter()
+            .map(|sprite| sprite.as_ref().map(encode_sprite)),
+    );
+    let sound_start = pages.len();
+    let mut info = Vec::with_capacity(contents.sounds.len() * 4);
+    for sound in &contents.sounds {
+        info.extend(((pages.len() - sound_start) as u16).to_le_bytes());
+        // only the low bits of the length fit, the rest comes from the page sizes
+        info.extend((sound.samples.len() as u16).to_le_bytes());
+        if sound.samples.is_empty() {
+            // an empty page, so the next sound doesn't start where this one does
+            pages.push(Some(Vec::new()));
+        }
+        pages.extend(
+            sound
+                .samples
+                .chunks(SOUND_PAGE_SIZE)
+                .map(|page| Some(page.to_vec())),
+        );
+    }
+    if !contents.sounds.is_empty() {
+        pages.push(Some(info));
+    }
+
+    if pages.len() > u16::MAX as usize {
+        return Err(invalid(format!(
+            "{} pages don't fit in a VSWAP file",
+            pages.len()
+        )));
+    }
+    if let Some(page) = pages
+        .iter()
+        .flatten()
+        .find(|page| page.len() > u16::MAX as usize)
+    {
+        return Err(invalid(format!(
+            "a page of {} bytes doesn't fit in a VSWAP file",
+            page.len()
+        )));
+    }
+
+    let mut vswap = Vec::new();
+    vswap.extend((pages.len() as u16).to_le_bytes());
+    vswap.extend((sprite_start as u16).to_le_bytes());
+    vswap.extend((sound_start as u16).to_le_bytes());
+    let mut offset = vswap.len() + pages.len() * 6;
+    let mut lengths = Vec::with_capacity(pages.len() * 2);
+    for page in &pages {
+        match page {
+            Some(data) => {
+                vswap.extend((offset as u32).to_le_bytes());
+                lengths.extend((data.len() as u16).to_le_bytes());
+                offset += data.len();
+            }
+            // sparse page
+            None => {
+                vswap.extend(0u32.to_le_bytes());
+                lengths.extend(0u16.to_le_bytes());
+            }
+        }
+    }
+    vswap.extend(lengths);
+    for page in pages.into_iter().flatten() {
+        vswap.extend(page);
+    }
+    Ok(vswap)
+}
+
+/// Writes a VSWAP file holding `contents` to the given path.
+pub fn write_vswap(contents: &VswapContents, path: &Path) -> io::Result<()> {
+    fs::File::create(path)?.write_all(&build_vswap(contents)?)
+}
+
+/// The words of a plane, row by row, `None` for a plane the map doesn't have.
+fn plane_bytes(map: &Map, plane: usize) -> Option<Vec<u8>> {
+    let words = map.plane(plane)?;
+    Some(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+}
+
+/// RLEW compresses a plane, prefixed with its size, and then Carmack compresses that.
+/// Fails for planes over 64 KiB, whose size doesn't fit in the prefix.
+fn compress_plane(bytes: &[u8], magic: &[u8; 2]) -> io::Result<Vec<u8>> {
+    let length = u16::try_from(bytes.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("a plane of {} bytes doesn't fit in GAMEMAPS", bytes.len()),
+        )
+    })?;
+    let mut rlew = length.to_le_bytes().to_vec();
+    rlew.extend(rlew_compress(bytes, magic));
+    carmack_compress(&rlew)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{self, ExternKind, Span};
+    use crate::mod_dir::tests::write_png;
+    use crate::mod_dir::ModDir;
+
+    fn test_map(seed: u16, name: &str) -> Map {
+        test_map_sized(64, 64, seed, name)
+    }
+
+    fn test_map_sized(width: usize, height: usize, seed: u16, name: &str) -> Map {
+        let mut plane0 = Vec::with_capacity(width * height);
+        let mut plane1 = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                plane0.push(if x % 7 == 0 || y % 5 == 0 {
+                    (x as u16 + seed) % 64 + 1
+                } else {
+                    108
+                });
+                plane1.push(if (x * y) % 11 == 0 {
+                    // some words that look like RLEW tags or Carmack pointers
+                    [RLEW_TAG, 0xA700, 0xA8FF, 19][(x + y) % 4]
+                } else {
+                    0
+                });
+            }
+        }
+        let plane2 = (0..width * height).map(|i| (i as u16 + seed) % 3).collect();
+        Map::new(width, height, plane0, plane1, name.to_string()).with_plane2(plane2)
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn map_files_read_back() {
+        let maps = vec![
+            test_map(0, "Wolf1 Map1"),
+            test_map(3, "A name too long to fit"),
+        ];
+        let dir = temp_dir("rustenstein_map_files_test");
+        let (map_head, game_maps) = (dir.join("MAPHEAD.WL1"), dir.join("GAMEMAPS.WL1"));
+        write_map_files(&maps, &map_head, &game_maps).unwrap();
+
+        let read = cache::read_maps(&map_head, &game_maps).unwrap();
+        assert_eq!(2, read.len());
+        assert_eq!(maps[0], read[0]);
+        let mut truncated = maps[1].clone();
+        truncated.name = "A name too long".to_string();
+        assert_eq!(truncated, read[1]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn odd_sized_maps_read_back() {
+        let maps = vec![
+            test_map_sized(128, 128, 1, "Big"),
+            test_map_sized(100, 30, 2, "Wide"),
+        ];
+        let dir = temp_dir("rustenstein_odd_map_files_test");
+        let (map_head, game_maps) = (dir.join("MAPHEAD.WL6"), dir.join("GAMEMAPS.WL6"));
+        write_map_files(&maps, &map_head, &game_maps).unwrap();
+
+        let read = cache::read_maps(&map_head, &game_maps).unwrap();
+        assert_eq!((100, 30), (read[1].width(), read[1].height()));
+        assert_eq!(maps, read);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn maps_without_plane2_read_back() {
+        let with_plane2 = test_map(0, "Map");
+        let maps = vec![Map::new(
+            64,
+            64,
+            with_plane2.plane(0).unwrap().to_vec(),
+            with_plane2.plane(1).unwrap().to_vec(),
+            "No plane 2".to_string(),
+        )];
+        let dir = temp_dir("rustenstein_no_plane2_test");
+        let (map_head, game_maps) = (dir.join("MAPHEAD.WL1"), dir.join("GAMEMAPS.WL1"));
+        write_map_files(&maps, &map_head, &game_maps).unwrap();
+
+        let read = cache::read_maps(&map_head, &game_maps).unwrap();
+        assert_eq!(None, read[0].plane(2));
+        assert_eq!(maps, read);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn planes_over_64k_are_refused() {
+        let err = build_map_files(&[test_map_sized(256, 256, 0, "Huge")]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn map_head_points_at_level_headers() {
+        let (map_head, game_maps) = build_map_files(&[test_map(0, "Map")]).unwrap();
+        assert_eq!(2 + 4 * MAX_MAPS, map_head.len());
+        assert_eq!(&[0xCD, 0xAB], &map_head[..2]);
+        let pointer = i32::from_le_bytes(map_head[2..6].try_into().unwrap()) as usize;
+        assert_eq!(&[0; 4], &map_head[6..10]);
+        assert_eq!(b"TED5v1.0", &game_maps[..8]);
+        assert_eq!(
+            8,
+            i32::from_le_bytes(game_maps[pointer..pointer + 4].try_into().unwrap())
+        );
+        assert_eq!(b"Map\0", &game_maps[pointer + 22..pointer + 26]);
+        assert_eq!(b"!ID!", &game_maps[game_maps.len() - 4..]);
+    }
+
+    // ignoring this one as it depends on the data files
+    /// The chunks of a small data set: 2 pictures, the 8x8 tiles and one more chunk.
+    fn test_graphics() -> (Vec<Vec<u8>>, ChunkLayout) {
+        let layout = ChunkLayout::with_externs(2, &[ExternKind::Article]);
+        let chunks = vec![
+            // the picture table, 8x2 and 4x1
+            vec![8, 0, 2, 0, 4, 0, 1, 0],
+            b"small font".to_vec(),
+            b"large font".to_vec(),
+            (0..16).collect(),
+            vec![0x0F; 4],
+            (0..64 * 72).map(|i| (i % 7) as u8).collect(),
+            b"^P\r\nan article^E".to_vec(),
+        ];
+        (chunks, layout)
+    }
+
+    #[test]
+    fn graphics_files_read_back() {
+        let (chunks, layout) = test_graphics();
+        let dir = temp_dir("rustenstein_graphics_files_test");
+        let (dict, head, graph) = (
+            dir.join("VGADICT.WL1"),
+            dir.join("VGAHEAD.WL1"),
+            dir.join("VGAGRAPH.WL1"),
+        );
+        write_graphics_files(&chunks, layout, &dict, &head, &graph).unwrap();
+
+        assert_eq!(1020, fs::metadata(&dict).unwrap().len());
+        assert_eq!(
+            (chunks.len() as u64 + 1) * 3,
+            fs::metadata(&head).unwrap().len()
+        );
+        assert_eq!(
+            chunks,
+            cache::read_graphics(&dict, &head, &graph, layout).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extern_chunks_are_read_as_their_kind() {
+        let dir = temp_dir("rustenstein_extern_kinds_test");
+        let layout = write_data_set(&dir, &test_vswap());
+
+        let cache = cache::init(&dir, cache::GameVariant::Shareware, layout).unwrap();
+        let article = layout.start_externs();
+        assert_eq!(
+            vec!["an article"],
+            cache.get_article(article).unwrap().pages
+        );
+        assert!(matches!(
+            cache.get_demo(article),
+            Err(CacheError::MissingChunk { .. })
+        ));
+        assert!(matches!(
+            cache.get_text_screen(article + 1),
+            Err(CacheError::MissingChunk { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn graphics_head_points_at_chunks() {
+        let (chunks, layout) = test_graphics();
+        let (_, head, graph) = build_graphics_files(&chunks, layout).unwrap();
+        let offset = |chunk: usize| {
+            let bytes = &head[chunk * 3..chunk * 3 + 3];
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) as usize
+        };
+        assert_eq!(0, offset(0));
+        assert_eq!(graph.len(), offset(chunks.len()));
+        // chunks start with their expanded length, except for the 8x8 tiles
+        let fonts = offset(1);
+        assert_eq!(&10u32.to_le_bytes(), &graph[fonts..fonts + 4]);
+        assert!(offset(6) - offset(5) < 64 * 72);
+    }
+
+    /// Writes a whole shareware data set around the given VSWAP contents, to read it
+    /// back with `cache::init`.
+    fn write_data_set(dir: &Path, contents: &VswapContents) -> ChunkLayout {
+        let file = |name: &str| dir.join(format!("{}.WL1", name));
+        write_vswap(contents, &file("VSWAP")).unwrap();
+        let (chunks, layout) = test_graphics();
+        write_graphics_files(
+            &chunks,
+            layout,
+            &file("VGADICT"),
+            &file("VGAHEAD"),
+            &file("VGAGRAPH"),
+        )
+        .unwrap();
+        write_map_files(&[test_map(0, "Map")], &file("MAPHEAD"), &file("GAMEMAPS")).unwrap();
+        // no sounds nor music, just enough empty chunks
+        fs::write(file("AUDIOHED"), vec![0; 4 * 1024]).unwrap();
+        fs::write(file("AUDIOT"), []).unwrap();
+        layout
+    }
+
+    fn test_sprite() -> Sprite {
+        Sprite {
+            left_pix: 30,
+            right_pix: 31,
+            columns: vec![
+                vec![Span {
+                    start: 0,
+                    pixels: (1..=64).collect(),
+                }],
+                vec![
+                    Span {
+                        start: 7,
+                        pixels: vec![9, 8],
+                    },
+                    Span {
+                        start: 40,
+                        pixels: vec![3],
+                    },
+                ],
+            ],
+        }
+    }
+
+    fn test_vswap() -> VswapContents {
+        VswapContents {
+            textures: vec![
+                Some((0..64 * 64).map(|i| (i % 251) as u8).collect()),
+                None,
+                Some(vec![7; 64 * 64]),
+            ],
+            sprites: vec![Some(test_sprite()), None],
+            sounds: vec![
+                DigiSound {
+                    samples: (0..10000).map(|i| (i % 256) as u8).collect(),
+                },
+                DigiSound { samples: vec![] },
+                DigiSound {
+                    samples: vec![0x80, 0x90, 0x70],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn vswap_reads_back_through_init() {
+        let contents = test_vswap();
+        let dir = temp_dir("rustenstein_vswap_test");
+        let layout = write_data_set(&dir, &contents);
+
+        let cache = cache::init(&dir, cache::GameVariant::Shareware, layout).unwrap();
+        assert_eq!(
+            (3, 2, 3),
+            (
+                cache.num_textures(),
+                cache.num_sprites(),
+                cache.num_sounds()
+            )
+        );
+        assert_eq!(contents.textures[0].as_ref(), cache.get_texture(0).ok());
+        assert!(matches!(
+            cache.get_texture(1),
+            Err(CacheError::MissingChunk { index: 1, .. })
+        ));
+        assert_eq!(contents.textures[2].as_ref(), cache.get_texture(2).ok());
+        assert_eq!(&test_sprite(), cache.get_sprite(0).unwrap());
+        assert!(cache.get_sprite(1).is_err());
+        for (index, sound) in contents.sounds.iter().enumerate() {
+            assert_eq!(sound.samples, cache.get_sound(index).unwrap().samples);
+        }
+        assert!(matches!(
+            cache.get_sound(3),
+            Err(CacheError::MissingSound {
+                index: 3,
+                count: 3,
+                ..
+            })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn short_texture_pages_are_truncated_chunks() {
+        let mut contents = test_vswap();
+        contents.textures[2] = Some(vec![7; 100]);
+        let dir = temp_dir("rustenstein_short_texture_test");
+        let layout = write_data_set(&dir, &contents);
+
+        let cache = cache::init(&dir, cache::GameVariant::Shareware, layout).unwrap();
+        assert!(cache.get_texture(0).is_ok());
+        assert!(matches!(
+            cache.get_texture(2),
+            Err(CacheError::TruncatedChunk { length: 4096, .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn vswap_header_and_sound_list() {
+        let vswap = build_vswap(&test_vswap()).unwrap();
+        let word = |offset: usize| u16::from_le_bytes([vswap[offset], vswap[offset + 1]]);
+        // 3 textures, 2 sprites, 3 + 1 + 1 sound pages and the sound list
+        let pages = 3 + 2 + 5 + 1;
+        assert_eq!((pages, 3, 5), (word(0), word(2), word(4)));
+
+        let lengths = 6 + pages as usize * 4;
+        let page_length = |page: usize| word(lengths + page * 2);
+        assert_eq!(0, page_length(1));
+        assert_eq!(&[0; 4], &vswap[6 + 4..6 + 8]);
+        assert_eq!(4096, page_length(5));
+        assert_eq!(10000 - 2 * 4096, page_length(7) as usize);
+        assert_eq!(0, page_length(8));
+
+        // first page, relative to the sound pages, and length of every sound
+        let list = vswap.len() - 12;
+        let list_words: Vec<u16> = (0..6).map(|i| word(list + i * 2)).collect();
+        assert_eq!(vec![0, 10000, 3, 0, 4, 3], list_words);
+    }
+
+    #[test]
+    fn png_assets_go_into_the_vswap() {
+        let dir = temp_dir("rustenstein_vswap_import_test");
+        let layout = write_data_set(&dir, &test_vswap());
+
+        // a texture where there was none, and a sprite of a single red pixel
+        let mut color_map = [(0, 0, 0); 256];
+        color_map[40] = (255, 0, 0);
+        color_map[41] = (0, 255, 0);
+        let mods = dir.join("mods");
+        write_png(
+            &mods.join("textures/001.png"),
+            64,
+            64,
+            &[0, 255, 0, 255].repeat(64 * 64),
+        );
+        let mut rgba = vec![0; 64 * 64 * 4];
+        let pixel = (5 * 64 + 9) * 4;
+        rgba[pixel..pixel + 4].copy_from_slice(&[250, 10, 0, 255]);
+        write_png(&mods.join("sprites/001.png"), 64, 64, &rgba);
+
+        let cache = cache::init(&dir, cache::GameVariant::Shareware, layout)
+            .unwrap()
+            .with_mod_dir(ModDir::new(&mods, color_map));
+        let contents = VswapContents::from_cache(&cache).unwrap();
+        drop(cache);
+        write_vswap(&contents, &dir.join("VSWAP.WL1")).unwrap();
+
+        let cache = cache::init(&dir, cache::GameVariant::Shareware, layout).unwrap();
+        assert_eq!(&vec![41; 64 * 64], cache.get_texture(1).unwrap());
+        assert_eq!(contents.textures[2].as_ref(), cache.get_texture(2).ok());
+        let sprite = cache.get_sprite(1).unwrap();
+        assert_eq!((9, 9), (sprite.left_pix, sprite.right_pix));
+        assert_eq!(Some(40), sprite.pixel(9, 5));
+        assert_eq!(&test_sprite(), cache.get_sprite(0).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn shipped_levels_round_trip() {
+        let data = std::path::Path::new("data");
+        let variant = cache::detect_variant(data).unwrap();
+        let layout = crate::version::fingerprint(data, variant).unwrap().layout();
+        let cache = cache::init(data, variant, layout).unwrap();
+        let maps: Vec<Map> = (0..cache.num_maps())
+            .map(|i| cache.get_map(i / 10, i % 10).unwrap())
+            .collect();
+
+        let dir = temp_dir("rustenstein_shipped_maps_test");
+        let (map_head, game_maps) = (dir.join("MAPHEAD"), dir.join("GAMEMAPS"));
+        write_map_files(&maps, &map_head, &game_maps).unwrap();
+        assert_eq!(maps, cache::read_maps(&map_head, &game_maps).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn shipped_graphics_round_trip() {
+        let data = std::path::Path::new("data");
+        let variant = cache::detect_variant(data).unwrap();
+        let layout = crate::version::fingerprint(data, variant).unwrap().layout();
+        let file = |name| cache::data_file(data, name, variant);
+        let chunks = cache::read_graphics(
+            &file("VGADICT"),
+            &file("VGAHEAD"),
+            &file("VGAGRAPH"),
+            layout,
+        )
+        .unwrap();
+
+        let dir = temp_dir("rustenstein_shipped_graphics_test");
+        let (dict, head, graph) = (
+            dir.join("VGADICT"),
+            dir.join("VGAHEAD"),
+            dir.join("VGAGRAPH"),
+        );
+        write_graphics_files(&chunks, layout, &dict, &head, &graph).unwrap();
+        assert_eq!(
+            chunks,
+            cache::read_graphics(&dict, &head, &graph, layout).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}